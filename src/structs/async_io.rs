@@ -0,0 +1,77 @@
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_util::io::SyncIoBridge;
+
+use super::{
+    forgemod::{surface_error, ContainerReader, ForgeMod, ForgeModData, Step, UnpackLimits},
+    manifest::{ManifestComponent, ManifestVersion},
+    unpack::{read_versioned, AnyForgeMod},
+    v1::{peek_v1_container, ForgeManifestTypes},
+};
+
+impl<
+        Version: ManifestVersion + Serialize + for<'a> Deserialize<'a>,
+        Comp: ManifestComponent + Serialize + for<'a> Deserialize<'a>,
+        Inner: ForgeModData + Serialize + for<'a> Deserialize<'a>,
+    > ForgeMod<Version, Comp, Inner>
+{
+    /// Async version of [`Self::pack_to`].
+    ///
+    /// Entries are checked and compressed on the blocking pool one at a time, each with a copy
+    /// of its data, only the writing happens on the calling task.
+    pub async fn pack_to_async<W: AsyncWrite + Unpin>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        let mut packer = self.packer()?;
+        while let Some(step) = packer.next_step()? {
+            let chunk = match step {
+                Step::Chunk(chunk) => chunk,
+                Step::Entry(pending) => {
+                    let job = pending.job();
+                    let data = pending.entry.data.to_vec();
+                    let stored = tokio::task::spawn_blocking(move || job.run(&data)).await??;
+                    packer.entry_chunk(pending, stored)?
+                },
+            };
+            writer.write_all(&chunk).await?;
+        }
+
+        writer.flush().await
+    }
+}
+
+/// Async version of [`unpack_forgemod_from`](super::unpack::unpack_forgemod_from).
+///
+/// The package is read and decompressed on the blocking pool while it streams in,
+/// it is never buffered whole.
+pub async fn unpack_forgemod_async<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+) -> Result<AnyForgeMod, Box<dyn Error + Send + Sync>> {
+    unpack_forgemod_async_with_limits(reader, &UnpackLimits::default()).await
+}
+
+/// [`unpack_forgemod_async`] with other limits than the default ones.
+pub async fn unpack_forgemod_async_with_limits<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+    limits: &UnpackLimits,
+) -> Result<AnyForgeMod, Box<dyn Error + Send + Sync>> {
+    let reader = SyncIoBridge::new(reader);
+    let limits = *limits;
+
+    blocking(move || Ok(read_versioned(reader, &limits)?.migrate())).await
+}
+
+/// Async version of [`peek_manifest_from`](super::v1::peek_manifest_from), nothing past the manifest is read.
+pub async fn peek_manifest_async<R: AsyncRead + Unpin + Send + 'static>(
+    reader: R,
+) -> Result<ForgeManifestTypes, Box<dyn Error + Send + Sync>> {
+    let reader = SyncIoBridge::new(reader);
+
+    blocking(move || peek_v1_container(ContainerReader::open(reader, &UnpackLimits::default()).map_err(surface_error)?)).await
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, Box<dyn Error + Send + Sync>> + Send + 'static,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    tokio::task::spawn_blocking(f).await?
+}
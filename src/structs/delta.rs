@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    io::Read,
+    marker::PhantomData,
+};
+
+use bincode::{serialize, Options};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    forgemod::{
+        encode_manifest, invalid_data, manifest_bytes, sha256, Checksums, Codec, Compression, Digest, Entry, EntryKind, FileAttrs, ForgeMod,
+        ForgeModData, PackageSignature, UnpackLimits,
+    },
+    manifest::{ForgeManifestSafe, ManifestComponent, ManifestVersion},
+};
+
+/// Magic bytes every packed [`DeltaPackage`] starts with.
+pub const DELTA_MAGIC: [u8; 8] = *b"FORGEDLT";
+
+/// Version of the delta layout written by [`DeltaPackage::pack`].
+pub const DELTA_VERSION: u16 = 2;
+
+/// How an entry of the new version is rebuilt from the old one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Patch {
+    /// Taken as is from the blob of the old version with this digest.
+    Base(Digest),
+    /// bsdiff patch against the blob of the old version with this digest.
+    Diff {
+        base: Digest,
+        #[serde(with = "serde_bytes")]
+        patch: Vec<u8>,
+    },
+    /// Nothing in the old version to start from.
+    Full(#[serde(with = "serde_bytes")] Vec<u8>),
+}
+
+/// One entry of the new version, in the order [`ForgeModData::entries`] lists them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaEntry {
+    pub kind: EntryKind,
+    pub path: String,
+    /// Digest of the rebuilt entry.
+    pub sha256: Digest,
+    pub patch: Patch,
+    pub attrs: FileAttrs,
+}
+
+/// Turns one version of a forge mod into the next, made with [`ForgeMod::diff`].
+///
+/// Only the entries that changed are carried, as binary patches against the old
+/// version. The canonical digests of both versions are kept, so a delta is never
+/// applied to the wrong base and always gives back exactly the new version.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeltaPackage<Version: ManifestVersion, Comp: ManifestComponent, Inner: ForgeModData> {
+    /// Canonical digest of the version the delta applies to.
+    pub base: Digest,
+    /// Canonical digest of the version it produces.
+    pub target: Digest,
+
+    #[serde(
+        with = "manifest_bytes",
+        bound(
+            serialize = "Version: Serialize, Comp: Serialize",
+            deserialize = "Version: for<'a> Deserialize<'a>, Comp: for<'a> Deserialize<'a>"
+        )
+    )]
+    pub manifest: ForgeManifestSafe<Comp, Version>,
+    /// Bincode encoding of the data metadata of the new version.
+    #[serde(with = "serde_bytes")]
+    meta: Vec<u8>,
+    pub entries: Vec<DeltaEntry>,
+
+    /// Compression of the new version, also used to pack the delta itself.
+    pub compression: Compression,
+    pub signature: Option<PackageSignature>,
+
+    #[serde(skip)]
+    _marker: PhantomData<Inner>,
+}
+
+/// Why a [`DeltaPackage`] could not be applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeltaError {
+    /// The mod is not the version the delta was made from.
+    WrongBase,
+    /// An entry refers to a blob the old version does not have.
+    MissingBlob { entry: String },
+    /// The patch of an entry does not give back the recorded digest.
+    Corrupt { entry: String },
+    /// The rebuilt mod is not the version the delta was made for.
+    Mismatch,
+}
+
+impl Display for DeltaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongBase => write!(f, "delta does not apply to this version"),
+            Self::MissingBlob { entry } => write!(f, "base of {} is missing", entry),
+            Self::Corrupt { entry } => write!(f, "patch of {} is corrupt", entry),
+            Self::Mismatch => write!(f, "delta does not produce the expected version"),
+        }
+    }
+}
+
+impl std::error::Error for DeltaError {}
+
+impl<
+        Version: ManifestVersion + Clone + Serialize + for<'a> Deserialize<'a>,
+        Comp: ManifestComponent + Clone + Serialize + for<'a> Deserialize<'a>,
+        Inner: ForgeModData + Serialize + for<'a> Deserialize<'a>,
+    > ForgeMod<Version, Comp, Inner>
+{
+    /// Makes the delta that turns `old` into `new`.
+    ///
+    /// Entries found in `old` with the same digest are referenced, entries at the same
+    /// path get a binary patch and everything else is stored in full.
+    pub fn diff(old: &Self, new: &Self) -> DeltaPackage<Version, Comp, Inner> {
+        let old_entries = old.data.entries();
+        let by_digest: HashMap<Digest, &[u8]> = old_entries.iter().map(|e| (sha256(e.data), e.data)).collect();
+        let by_path: HashMap<(EntryKind, &str), &[u8]> = old_entries.iter().map(|e| ((e.kind, e.path.as_ref()), e.data)).collect();
+
+        let entries = new
+            .data
+            .entries()
+            .into_iter()
+            .map(|entry| {
+                let digest = sha256(entry.data);
+                let patch = if by_digest.contains_key(&digest) {
+                    Patch::Base(digest)
+                } else if let Some(base) = by_path.get(&(entry.kind, entry.path.as_ref())) {
+                    let mut patch = Vec::new();
+                    bsdiff::diff(base, entry.data, &mut patch).expect("writing to a Vec cannot fail");
+                    Patch::Diff {
+                        base: sha256(base),
+                        patch,
+                    }
+                } else {
+                    Patch::Full(entry.data.to_vec())
+                };
+
+                DeltaEntry {
+                    kind: entry.kind,
+                    path: entry.path.into_owned(),
+                    sha256: digest,
+                    patch,
+                    attrs: entry.attrs,
+                }
+            })
+            .collect();
+
+        DeltaPackage {
+            base: old.canonical_digest(),
+            target: new.canonical_digest(),
+            manifest: new.manifest.clone(),
+            meta: serialize(&new.data.meta()).expect("mods always serialize"),
+            entries,
+            compression: new.compression,
+            signature: new.signature.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<
+        Version: ManifestVersion + Clone + Serialize + for<'a> Deserialize<'a>,
+        Comp: ManifestComponent + Clone + Serialize + for<'a> Deserialize<'a>,
+        Inner: ForgeModData + Serialize + for<'a> Deserialize<'a>,
+    > DeltaPackage<Version, Comp, Inner>
+{
+    /// Rebuilds the new version from `old`, which has to be the version the delta was made from.
+    pub fn apply(&self, old: &ForgeMod<Version, Comp, Inner>) -> Result<ForgeMod<Version, Comp, Inner>, DeltaError> {
+        if old.canonical_digest() != self.base {
+            return Err(DeltaError::WrongBase);
+        }
+
+        let old_entries = old.data.entries();
+        let blobs: HashMap<Digest, &[u8]> = old_entries.iter().map(|e| (sha256(e.data), e.data)).collect();
+
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let base = |digest| {
+                    blobs.get(digest).ok_or_else(|| DeltaError::MissingBlob {
+                        entry: entry.path.clone(),
+                    })
+                };
+                let corrupt = || DeltaError::Corrupt {
+                    entry: entry.path.clone(),
+                };
+
+                let data = match &entry.patch {
+                    Patch::Base(digest) => base(digest)?.to_vec(),
+                    Patch::Diff { base: digest, patch } => {
+                        let mut data = Vec::new();
+                        bsdiff::patch(base(digest)?, &mut patch.as_slice(), &mut data).map_err(|_| corrupt())?;
+                        data
+                    },
+                    Patch::Full(data) => data.clone(),
+                };
+                if sha256(&data) != entry.sha256 {
+                    return Err(corrupt());
+                }
+
+                Ok(Entry {
+                    kind: entry.kind,
+                    path: entry.path.clone(),
+                    data: Bytes::from(data),
+                    attrs: entry.attrs,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let meta = bincode::deserialize(&self.meta).map_err(|_| DeltaError::Mismatch)?;
+        let data = Inner::from_entries(meta, entries).map_err(|_| DeltaError::Mismatch)?;
+        let raw_manifest = Bytes::from(encode_manifest(&self.manifest).map_err(|_| DeltaError::Mismatch)?);
+        let new = ForgeMod {
+            format_version: old.format_version,
+            kind: old.kind.clone(),
+            checksums: Checksums::compute(&raw_manifest, &data),
+            manifest: self.manifest.clone(),
+            data,
+            compression: self.compression,
+            signature: self.signature.clone(),
+            raw_manifest,
+            _marker: PhantomData,
+        };
+
+        match new.canonical_digest() == self.target {
+            true => Ok(new),
+            false => Err(DeltaError::Mismatch),
+        }
+    }
+
+    /// Packs the delta, compressed with [`Self::compression`].
+    pub fn pack(&self) -> Result<Bytes, std::io::Error> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&DELTA_MAGIC);
+        buf.extend_from_slice(&DELTA_VERSION.to_le_bytes());
+        buf.extend_from_slice(&[self.compression.codec as u8, self.compression.level]);
+        buf.extend(self.compression.compress(&serialize(self).map_err(std::io::Error::other)?)?);
+
+        Ok(Bytes::from(buf))
+    }
+
+    /// Reads a delta written by [`Self::pack`].
+    pub fn from_bytes<'a, T: Into<&'a [u8]>>(bytes: T) -> Result<Self, bincode::Error> {
+        let bytes = bytes.into();
+        let header_len = DELTA_MAGIC.len() + 4;
+        if bytes.len() < header_len || !bytes.starts_with(&DELTA_MAGIC) {
+            return Err(invalid_data("not a delta package".into()).into());
+        }
+
+        let header = &bytes[DELTA_MAGIC.len()..header_len];
+        let version = u16::from_le_bytes([header[0], header[1]]);
+        if version != DELTA_VERSION {
+            return Err(invalid_data(format!("unsupported delta version {}", version)).into());
+        }
+
+        let reader = Codec::try_from(header[2])?.decompress(&bytes[header_len..])?;
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .with_limit(UnpackLimits::default().max_total_size)
+            .deserialize_from(reader.take(UnpackLimits::default().max_total_size))
+    }
+}
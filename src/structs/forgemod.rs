@@ -1,7 +1,17 @@
-use std::marker::PhantomData;
+use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    io::{Cursor, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    rc::Rc,
+};
 
-use bincode::serialize;
+use bincode::{serialize, serialize_into, Options};
 use bytes::Bytes;
+use ed25519_dalek::Signer;
+use sha2::{Digest as _, Sha256};
 use xz2::{read::XzDecoder, write::XzEncoder};
 
 // use super::manifest::ForgeManifest;
@@ -9,6 +19,8 @@ use serde::{Deserialize, Serialize};
 
 use super::manifest::{ManifestComponent, ManifestVersion, ForgeManifestSafe};
 
+pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+
 /// Outer wrapper for forge mods.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ForgeMod<Version: ManifestVersion, Comp: ManifestComponent, Inner: ForgeModData> {
@@ -19,6 +31,22 @@ pub struct ForgeMod<Version: ManifestVersion, Comp: ManifestComponent, Inner: Fo
 
     pub data: Inner,
 
+    /// How [`ForgeMod::pack`] compresses the package, read back from the container header.
+    #[serde(skip)]
+    pub compression: Compression,
+
+    /// Digests recorded when the mod was built, checked again by pack and unpack.
+    #[serde(skip)]
+    pub checksums: Checksums,
+
+    /// Detached signature added by [`ForgeMod::sign`].
+    #[serde(skip)]
+    pub signature: Option<PackageSignature>,
+
+    /// The manifest as stored in the container, see [`ForgeMod::manifest_bytes`].
+    #[serde(skip)]
+    pub(crate) raw_manifest: Bytes,
+
     #[serde(skip)]
     pub(crate) _marker: PhantomData<Version>,
 }
@@ -31,13 +59,1153 @@ pub struct ForgeModGeneric {
 
 impl ForgeModGeneric {
     pub fn from_bytes<'a, T: Into<&'a [u8]>>(bytes: T) -> Result<Self, bincode::Error> {
-        let contents = XzDecoder::new(bytes.into()).into_inner();
-        bincode::deserialize(contents)
+        Self::read_from(bytes.into())
+    }
+
+    /// Reads only the generic header of a packed forge mod from `reader`.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, bincode::Error> {
+        Ok(ContainerReader::open(reader, &UnpackLimits::default())?.generic)
+    }
+}
+
+/// Signature every packed forge mod starts with.
+pub const MAGIC: [u8; 8] = *b"FORGEMOD";
+
+/// Container version written by [`ForgeMod::pack`].
+/// Version `0` is reserved for packages published before the container had a header,
+/// version `1` entries carry no [`FileAttrs`].
+pub const CONTAINER_VERSION: u16 = 2;
+
+/// Length of the fixed header: magic, container version, codec and level.
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Compression applied to the payload of a container.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum Codec {
+    None = 0,
+    Xz = 1,
+    Zstd = 2,
+}
+
+impl TryFrom<u8> for Codec {
+    type Error = std::io::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Xz),
+            2 => Ok(Self::Zstd),
+            _ => Err(invalid_data(format!("unknown codec {}", value))),
+        }
+    }
+}
+
+/// Codec and level a package is packed with.
+///
+/// Levels are clamped to what the codec supports, `0..=9` for xz and `1..=22` for zstd.
+/// The default is the smallest output, xz at level 9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Compression {
+    pub codec: Codec,
+    pub level: u8,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new(Codec::Xz, 9)
+    }
+}
+
+impl Compression {
+    pub fn new(codec: Codec, level: u8) -> Self {
+        let level = match codec {
+            Codec::None => 0,
+            Codec::Xz => level.min(9),
+            Codec::Zstd => level.clamp(1, 22),
+        };
+
+        Self { codec, level }
+    }
+
+    /// Quick to pack, meant for CI builds.
+    pub fn fast() -> Self {
+        Self::new(Codec::Zstd, 3)
+    }
+
+    pub(crate) fn compress(self, data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        match self.codec {
+            Codec::None => Ok(data.to_vec()),
+            Codec::Xz => {
+                let mut encoder = XzEncoder::new(Vec::new(), self.level as u32);
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+            Codec::Zstd => zstd::encode_all(data, self.level as i32),
+        }
+    }
+}
+
+impl Codec {
+    pub(crate) fn decompress<'r, R: Read + 'r>(self, reader: R) -> Result<Box<dyn Read + 'r>, std::io::Error> {
+        match self {
+            Self::None => Ok(Box::new(reader)),
+            Self::Xz => Ok(Box::new(XzDecoder::new(reader))),
+            Self::Zstd => Ok(Box::new(zstd::Decoder::new(reader)?)),
+        }
+    }
+}
+
+/// What [`sniff`] found out about a packed forge mod.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub version: u16,
+    pub compression: Compression,
+}
+
+/// Checks whether `bytes` look like a packed forge mod without unpacking it.
+///
+/// Only the first few bytes are looked at, so this is cheap enough to run on
+/// every upload. Packages from before the header existed report version `0`.
+pub fn sniff(bytes: &[u8]) -> Option<ContainerInfo> {
+    if bytes.starts_with(&MAGIC) {
+        return parse_header(bytes.get(..HEADER_LEN)?).ok();
+    }
+
+    is_legacy(bytes).then_some(ContainerInfo {
+        version: 0,
+        compression: Compression::new(Codec::None, 0),
+    })
+}
+
+/// Legacy packages are a bare bincode [`ForgeMod`], the only format version
+/// ever written that way is `1` and the kind is a short lowercase word.
+fn is_legacy(bytes: &[u8]) -> bool {
+    let Some(format_version) = bytes.get(..4) else {
+        return false;
+    };
+    let Some(kind_len) = bytes.get(4..12) else {
+        return false;
+    };
+    let kind_len = u64::from_le_bytes(kind_len.try_into().unwrap()) as usize;
+    let Some(kind) = bytes.get(12..12 + kind_len.min(32)) else {
+        return false;
+    };
+
+    format_version == 1u32.to_le_bytes()
+        && kind_len > 0
+        && kind_len <= 32
+        && kind.iter().all(|c| c.is_ascii_lowercase() || *c == b'_')
+}
+
+fn parse_header(header: &[u8]) -> Result<ContainerInfo, std::io::Error> {
+    let version = u16::from_le_bytes([header[MAGIC.len()], header[MAGIC.len() + 1]]);
+    if !(1..=CONTAINER_VERSION).contains(&version) {
+        return Err(invalid_data(format!("unsupported container version {}", version)));
+    }
+
+    Ok(ContainerInfo {
+        version,
+        compression: Compression::new(Codec::try_from(header[MAGIC.len() + 2])?, header[MAGIC.len() + 3]),
+    })
+}
+
+fn write_header<W: Write>(mut writer: W, compression: Compression) -> Result<(), std::io::Error> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&CONTAINER_VERSION.to_le_bytes())?;
+    writer.write_all(&[compression.codec as u8, compression.level])
+}
+
+pub(crate) fn invalid_data(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// Writes `value` as a bincode section prefixed with its length, returns the bytes written.
+fn write_section<W: Write, T: Serialize>(writer: W, value: &T) -> Result<u64, std::io::Error> {
+    write_raw_section(writer, &serialize(value).map_err(std::io::Error::other)?)
+}
+
+fn write_raw_section<W: Write>(mut writer: W, section: &[u8]) -> Result<u64, std::io::Error> {
+    let len = u32::try_from(section.len()).map_err(|_| invalid_data("section is too large".into()))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(section)?;
+
+    Ok(4 + section.len() as u64)
+}
+
+/// Reads a section written by [`write_raw_section`], failing if it is larger than `max` bytes.
+fn read_raw_section<R: Read>(mut reader: R, max: u64) -> Result<Vec<u8>, bincode::Error> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as u64;
+    if len > max {
+        return Err(LimitError::TotalSize { limit: max }.into());
+    }
+
+    let mut section = Vec::new();
+    reader.take(len).read_to_end(&mut section)?;
+    if section.len() as u64 != len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
     }
+
+    Ok(section)
+}
+
+/// Bincode options matching [`bincode::deserialize`], but refusing to read more than `limit` bytes.
+///
+/// Reading straight from a stream needs this, otherwise a forged length prefix
+/// makes bincode allocate whatever it claims up front.
+fn limited(limit: u64) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(limit)
 }
 
-/// Marker trait for forge mod data.
-pub trait ForgeModData {}
+/// Replaces the size limit error of [`limited`] with the limit that was actually hit.
+fn size_limit_as(err: bincode::Error, limit: LimitError) -> bincode::Error {
+    if matches!(*err, bincode::ErrorKind::SizeLimit) {
+        return limit.into();
+    }
+
+    err
+}
+
+/// Caps on what unpacking an untrusted package may allocate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnpackLimits {
+    /// Manifest, metadata and every entry once decompressed, all together.
+    pub max_total_size: u64,
+    pub max_include_count: usize,
+    /// Any single entry once decompressed.
+    pub max_entry_size: u64,
+    /// Length in bytes of an include `dest` or any path in a manifest.
+    pub max_path_len: usize,
+}
+
+impl Default for UnpackLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: 1 << 30,
+            max_include_count: 10_000,
+            max_entry_size: 512 << 20,
+            max_path_len: 1024,
+        }
+    }
+}
+
+impl UnpackLimits {
+    /// No limits at all, only for packages from a trusted source.
+    pub fn unlimited() -> Self {
+        Self {
+            max_total_size: u64::MAX,
+            max_include_count: usize::MAX,
+            max_entry_size: u64::MAX,
+            max_path_len: usize::MAX,
+        }
+    }
+
+    pub(crate) fn check_path<P: AsRef<std::ffi::OsStr>>(&self, path: P) -> Result<(), LimitError> {
+        if path.as_ref().len() > self.max_path_len {
+            return Err(LimitError::PathLength { limit: self.max_path_len });
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn check_include_count(&self, count: usize) -> Result<(), LimitError> {
+        if count > self.max_include_count {
+            return Err(LimitError::IncludeCount { limit: self.max_include_count });
+        }
+
+        Ok(())
+    }
+}
+
+/// A package went over one of its [`UnpackLimits`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LimitError {
+    TotalSize { limit: u64 },
+    IncludeCount { limit: usize },
+    EntrySize { entry: String, limit: u64 },
+    PathLength { limit: usize },
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TotalSize { limit } => write!(f, "package unpacks to more than {} bytes", limit),
+            Self::IncludeCount { limit } => write!(f, "package has more than {} includes", limit),
+            Self::EntrySize { entry, limit } => write!(f, "{} unpacks to more than {} bytes", entry, limit),
+            Self::PathLength { limit } => write!(f, "package has a path longer than {} bytes", limit),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+impl From<LimitError> for std::io::Error {
+    fn from(err: LimitError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl From<LimitError> for bincode::Error {
+    fn from(err: LimitError) -> Self {
+        Box::new(bincode::ErrorKind::Io(err.into()))
+    }
+}
+
+/// Counts the bytes read through it.
+struct Counter<R> {
+    inner: R,
+    read: u64,
+}
+
+impl<R: Read> Read for Counter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+
+        Ok(n)
+    }
+}
+
+/// Reads from a shared buffer whose position is also known to the [`ContainerReader`],
+/// so stored entries can be sliced out of the buffer instead of copied.
+struct SharedCursor {
+    bytes: Bytes,
+    pos: Rc<Cell<usize>>,
+}
+
+impl SharedCursor {
+    /// Hands out the next `len` bytes without copying them.
+    fn slice(&self, len: u64) -> Result<Bytes, std::io::Error> {
+        let start = self.pos.get();
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| start.checked_add(len))
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(std::io::ErrorKind::UnexpectedEof)?;
+        self.pos.set(end);
+
+        Ok(self.bytes.slice(start..end))
+    }
+}
+
+impl Read for SharedCursor {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let start = self.pos.get().min(self.bytes.len());
+        let n = buf.len().min(self.bytes.len() - start);
+        buf[..n].copy_from_slice(&self.bytes[start..start + n]);
+        self.pos.set(start + n);
+
+        Ok(n)
+    }
+}
+
+/// SHA-256 digest of a manifest or entry.
+pub type Digest = [u8; 32];
+
+pub(crate) fn sha256(data: &[u8]) -> Digest {
+    Sha256::digest(data).into()
+}
+
+/// Digests of everything stored in a package.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Checksums {
+    /// Digest of the CBOR encoded manifest as stored in the package.
+    pub manifest: Digest,
+    /// One digest per entry, in the order of [`ForgeModData::entries`].
+    pub entries: Vec<Digest>,
+}
+
+impl Checksums {
+    pub(crate) fn compute<D: ForgeModData>(manifest: &[u8], data: &D) -> Self {
+        Self {
+            manifest: sha256(manifest),
+            entries: data.entries().iter().map(|e| sha256(e.data)).collect(),
+        }
+    }
+}
+
+/// The digest recorded for an entry does not match its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumError {
+    /// `manifest`, `artifact` or the `dest` of an include.
+    pub entry: String,
+}
+
+impl Display for ChecksumError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "checksum mismatch for {}", self.entry)
+    }
+}
+
+impl std::error::Error for ChecksumError {}
+
+impl From<ChecksumError> for std::io::Error {
+    fn from(err: ChecksumError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl From<ChecksumError> for bincode::Error {
+    fn from(err: ChecksumError) -> Self {
+        Box::new(bincode::ErrorKind::Io(err.into()))
+    }
+}
+
+/// Errors of the sync API, the internals keep them `Send + Sync` for the async one.
+pub(crate) fn local_error(err: Box<dyn std::error::Error + Send + Sync>) -> Box<dyn std::error::Error> {
+    err
+}
+
+/// Unwraps typed errors like [`ChecksumError`] and [`LimitError`] from the bincode error
+/// they were passed up in, so callers can downcast them.
+pub(crate) fn surface_error(err: bincode::Error) -> Box<dyn std::error::Error + Send + Sync> {
+    let typed = matches!(
+        &*err,
+        bincode::ErrorKind::Io(io) if io.get_ref().is_some_and(|e| e.is::<ChecksumError>() || e.is::<LimitError>())
+    );
+    if !typed {
+        return err;
+    }
+
+    match *err {
+        bincode::ErrorKind::Io(io) => io.into_inner().unwrap(),
+        _ => unreachable!(),
+    }
+}
+
+fn check(expected: &Digest, data: &[u8], entry: impl FnOnce() -> String) -> Result<(), ChecksumError> {
+    if sha256(data) != *expected {
+        return Err(ChecksumError { entry: entry() });
+    }
+
+    Ok(())
+}
+
+/// Signature over the canonical bytes of a package, see [`ForgeMod::sign`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageSignature {
+    /// Key the package was signed with.
+    pub key: VerifyingKey,
+    pub signature: Signature,
+}
+
+/// Why [`ForgeMod::verify`] rejected a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    Unsigned,
+    /// The package was signed with another key.
+    WrongKey,
+    /// The package changed after it was signed.
+    Invalid,
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsigned => write!(f, "package is not signed"),
+            Self::WrongKey => write!(f, "package is signed with another key"),
+            Self::Invalid => write!(f, "package signature does not match its contents"),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// Hashes everything read through it and fails at the end if the digest does not match.
+struct VerifyingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    expected: Digest,
+    entry: String,
+}
+
+impl<R: Read> Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+
+        if n == 0 && !buf.is_empty() && <[u8; 32]>::from(self.hasher.clone().finalize()) != self.expected {
+            return Err(ChecksumError { entry: self.entry.clone() }.into());
+        }
+
+        Ok(n)
+    }
+}
+
+/// What a blob stored in a container belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EntryKind {
+    Artifact,
+    Include,
+}
+
+/// Name an entry is reported under, `artifact` or the `dest` of an include.
+/// Artifacts of embedded modules are reported under the module `_id`.
+fn entry_name(kind: EntryKind, path: &str) -> String {
+    match kind {
+        EntryKind::Artifact if path.is_empty() => "artifact".into(),
+        EntryKind::Artifact => format!("{}/artifact", path),
+        EntryKind::Include => path.into(),
+    }
+}
+
+/// A blob handed to the container by [`ForgeModData::entries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryRef<'a> {
+    pub kind: EntryKind,
+    /// `dest` of an include, empty for the artifact.
+    pub path: Cow<'a, str>,
+    pub data: &'a [u8],
+    pub attrs: FileAttrs,
+}
+
+/// A blob read back from a container, handed to [`ForgeModData::from_entries`].
+///
+/// Entries of uncompressed packages read with [`ForgeMod::from_shared`] point into the packed bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: EntryKind,
+    pub path: String,
+    pub data: Bytes,
+    pub attrs: FileAttrs,
+}
+
+/// File metadata of an entry, stored next to its digest and applied when it is extracted.
+///
+/// Both are left unset unless asked for, so building from the same files on
+/// another machine or at another time packs the same bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileAttrs {
+    /// Unix permission bits, extracted files get the default ones without.
+    pub mode: Option<u32>,
+    /// Modification time in seconds since the Unix epoch, extracted files keep the
+    /// time they were written at without.
+    pub mtime: Option<u64>,
+}
+
+impl FileAttrs {
+    /// Sets what is recorded on the file at `path`, permissions only on Unix.
+    ///
+    /// Only the permission bits of `mode` are applied, setuid, setgid and sticky are dropped.
+    pub fn apply(&self, path: &std::path::Path) -> Result<(), std::io::Error> {
+        let mtime = match self.mtime {
+            Some(secs) => Some(
+                std::time::UNIX_EPOCH
+                    .checked_add(std::time::Duration::from_secs(secs))
+                    .ok_or_else(|| invalid_data(format!("modification time {} is out of range", secs)))?,
+            ),
+            None => None,
+        };
+
+        #[cfg(unix)]
+        if let Some(mode) = self.mode {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+
+        if let Some(mtime) = mtime {
+            std::fs::File::options().write(true).open(path)?.set_modified(mtime)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Storage for the bytes of an artifact or include.
+///
+/// `Vec<u8>` owns its bytes, [`Bytes`] can share them with the buffer a package was
+/// read from, a memory mapped file for instance. Both are serialized the same way.
+pub trait Blob: AsRef<[u8]> + From<Bytes> + Clone + std::fmt::Debug + PartialEq {}
+
+impl Blob for Vec<u8> {}
+
+impl Blob for Bytes {}
+
+/// `serde(with)` module for [`Blob`] fields, the same encoding as `serde_bytes`.
+pub mod blob {
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Blob;
+
+    pub fn serialize<B: Blob, S: Serializer>(blob: &B, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(blob.as_ref())
+    }
+
+    pub fn deserialize<'de, B: Blob, D: Deserializer<'de>>(deserializer: D) -> Result<B, D::Error> {
+        serde_bytes::ByteBuf::deserialize(deserializer).map(|buf| B::from(Bytes::from(buf.into_vec())))
+    }
+}
+
+/// Encodes a manifest as CBOR.
+///
+/// Unlike the bincode used for everything else, CBOR is self-describing: readers skip
+/// fields they do not know, and `Option` fields missing from older manifests read as `None`.
+/// New manifest fields have to be optional or `#[serde(default)]` to keep that working.
+pub(crate) fn encode_manifest<M: Serialize>(manifest: &M) -> Result<Vec<u8>, std::io::Error> {
+    let mut buf = Vec::new();
+    ciborium::into_writer(manifest, &mut buf).map_err(std::io::Error::other)?;
+
+    Ok(buf)
+}
+
+pub(crate) fn decode_manifest<M: for<'a> Deserialize<'a>>(bytes: &[u8]) -> Result<M, std::io::Error> {
+    ciborium::from_reader(bytes).map_err(|e| invalid_data(format!("invalid manifest: {}", e)))
+}
+
+/// `serde(with)` module for manifests stored inside bincode sections, see [`encode_manifest`].
+pub(crate) mod manifest_bytes {
+    use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{decode_manifest, encode_manifest};
+
+    pub fn serialize<M: Serialize, S: Serializer>(manifest: &M, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&encode_manifest(manifest).map_err(S::Error::custom)?)
+    }
+
+    pub fn deserialize<'de, M: for<'a> Deserialize<'a>, D: Deserializer<'de>>(deserializer: D) -> Result<M, D::Error> {
+        decode_manifest(&serde_bytes::ByteBuf::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// Written in front of every blob so a container can be read front to back.
+///
+/// Blobs are stored once per digest, later entries with the same content only get a header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct EntryHeader {
+    kind: EntryKind,
+    path: String,
+    /// Size once decompressed.
+    len: u64,
+    /// Size of the stored blob that follows.
+    size: u64,
+    sha256: Digest,
+    /// The blob was already stored by an earlier entry with the same digest, nothing follows.
+    shared: bool,
+    attrs: FileAttrs,
+}
+
+/// [`EntryHeader`] as written to version `1` containers.
+#[derive(Deserialize)]
+struct EntryHeaderV1 {
+    kind: EntryKind,
+    path: String,
+    len: u64,
+    size: u64,
+    sha256: Digest,
+    shared: bool,
+}
+
+impl From<EntryHeaderV1> for EntryHeader {
+    fn from(h: EntryHeaderV1) -> Self {
+        Self {
+            kind: h.kind,
+            path: h.path,
+            len: h.len,
+            size: h.size,
+            sha256: h.sha256,
+            shared: h.shared,
+            attrs: FileAttrs::default(),
+        }
+    }
+}
+
+/// Blob stored by an earlier entry with the same digest as `header`.
+fn shared_blob(blobs: &HashMap<Digest, Bytes>, header: &EntryHeader) -> Result<Bytes, std::io::Error> {
+    match blobs.get(&header.sha256) {
+        Some(blob) if header.size == 0 => Ok(blob.clone()),
+        _ => Err(invalid_data(format!("entry {} refers to a missing blob", header.path))),
+    }
+}
+
+/// One record of the entry table at the end of a container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub kind: EntryKind,
+    pub path: String,
+    /// Offset of the stored blob from the start of the container,
+    /// entries with the same digest share one blob.
+    pub offset: u64,
+    /// Size of the stored blob.
+    pub size: u64,
+    /// Size once decompressed.
+    pub len: u64,
+    pub sha256: Digest,
+    pub attrs: FileAttrs,
+}
+
+/// [`IndexEntry`] as written to version `1` containers.
+#[derive(Deserialize)]
+struct IndexEntryV1 {
+    kind: EntryKind,
+    path: String,
+    offset: u64,
+    size: u64,
+    len: u64,
+    sha256: Digest,
+}
+
+impl From<IndexEntryV1> for IndexEntry {
+    fn from(e: IndexEntryV1) -> Self {
+        Self {
+            kind: e.kind,
+            path: e.path,
+            offset: e.offset,
+            size: e.size,
+            len: e.len,
+            sha256: e.sha256,
+            attrs: FileAttrs::default(),
+        }
+    }
+}
+
+/// A packed forge mod whose header has been read, handing out its sections in order.
+///
+/// After the fixed header come the uncompressed [`ForgeModGeneric`], then the
+/// CBOR manifest followed by its digest, the optional signature, the data metadata and the
+/// entry count as length prefixed bincode sections. Every blob follows with an [`EntryHeader`] carrying its
+/// digest, each compressed on its own with the container codec. The entry table and a trailing `u64` pointing at it
+/// close the container, see [`read_index`].
+///
+/// Packages written before the header existed are a bare bincode [`ForgeMod`]
+/// followed by an empty xz stream, so the same sections are read straight from the
+/// bincode stream and the trailing bytes are never consumed.
+///
+/// Everything read is counted against the [`UnpackLimits`] the reader was opened with.
+pub(crate) struct ContainerReader<'r> {
+    pub(crate) info: ContainerInfo,
+    pub(crate) generic: ForgeModGeneric,
+    /// Filled in as the sections are read and verified, legacy packages have none.
+    pub(crate) checksums: Checksums,
+    pub(crate) signature: Option<PackageSignature>,
+    pub(crate) limits: UnpackLimits,
+    /// What is left of [`UnpackLimits::max_total_size`].
+    budget: u64,
+    reader: Counter<Box<dyn Read + 'r>>,
+    /// Set when opened with [`Self::open_shared`], uncompressed entries are sliced out of it.
+    shared: Option<SharedCursor>,
+}
+
+/// More than enough for the format version and any kind.
+const GENERIC_LIMIT: u64 = 256;
+
+/// Everything in an [`EntryHeader`] besides the path.
+const ENTRY_HEADER_LEN: u64 = 4 + 8 + 8 + 8 + 32 + 1 + (1 + 4) + (1 + 8);
+
+impl<'r> ContainerReader<'r> {
+    pub(crate) fn open<R: Read + 'r>(mut reader: R, limits: &UnpackLimits) -> Result<Self, bincode::Error> {
+        let mut header = [0u8; HEADER_LEN];
+        let mut filled = 0;
+        while filled < header.len() {
+            match reader.read(&mut header[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+
+        let (info, mut reader): (_, Box<dyn Read + 'r>) = if header.starts_with(&MAGIC) {
+            if filled < HEADER_LEN {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            (parse_header(&header)?, Box::new(reader))
+        } else {
+            let legacy = ContainerInfo {
+                version: 0,
+                compression: Compression::new(Codec::None, 0),
+            };
+            (legacy, Box::new(Cursor::new(header[..filled].to_vec()).chain(reader)))
+        };
+
+        let generic = limited(GENERIC_LIMIT)
+            .deserialize_from(&mut reader)
+            .map_err(|e| match matches!(*e, bincode::ErrorKind::SizeLimit) {
+                true => invalid_data("package kind is too long".into()).into(),
+                false => e,
+            })?;
+
+        Ok(Self {
+            info,
+            generic,
+            checksums: Checksums::default(),
+            signature: None,
+            limits: *limits,
+            budget: limits.max_total_size,
+            reader: Counter { inner: reader, read: 0 },
+            shared: None,
+        })
+    }
+
+    /// Opens a container that is entirely in memory, see [`ForgeMod::from_shared`].
+    pub(crate) fn open_shared(bytes: Bytes, limits: &UnpackLimits) -> Result<Self, bincode::Error> {
+        let pos = Rc::new(Cell::new(0));
+        let mut container = Self::open(
+            SharedCursor {
+                bytes: bytes.clone(),
+                pos: pos.clone(),
+            },
+            limits,
+        )?;
+        container.shared = Some(SharedCursor { bytes, pos });
+
+        Ok(container)
+    }
+
+    /// Reads a section of a legacy package straight from the bincode stream.
+    fn legacy_section<T: for<'a> Deserialize<'a>>(&mut self) -> Result<T, bincode::Error> {
+        let start = self.reader.read;
+        let value = limited(self.budget)
+            .deserialize_from(&mut self.reader)
+            .map_err(|e| size_limit_as(e, LimitError::TotalSize { limit: self.limits.max_total_size }))?;
+        self.budget = self.budget.saturating_sub(self.reader.read - start);
+
+        Ok(value)
+    }
+
+    fn raw_section(&mut self) -> Result<Vec<u8>, bincode::Error> {
+        let section = read_raw_section(&mut self.reader, self.budget)
+            .map_err(|e| size_limit_as(e, LimitError::TotalSize { limit: self.limits.max_total_size }))?;
+        self.budget -= section.len() as u64;
+
+        Ok(section)
+    }
+
+    fn section<T: for<'a> Deserialize<'a>>(&mut self) -> Result<T, bincode::Error> {
+        bincode::deserialize(&self.raw_section()?)
+    }
+
+    pub(crate) fn manifest<M: Serialize + for<'a> Deserialize<'a>>(&mut self) -> Result<M, bincode::Error> {
+        Ok(self.stored_manifest()?.0)
+    }
+
+    /// The manifest along with the bytes it was stored as, legacy packages get them encoded.
+    fn stored_manifest<M: Serialize + for<'a> Deserialize<'a>>(&mut self) -> Result<(M, Bytes), bincode::Error> {
+        if self.info.version == 0 {
+            let manifest = self.legacy_section()?;
+            let raw = encode_manifest(&manifest)?;
+            return Ok((manifest, Bytes::from(raw)));
+        }
+
+        let raw = self.raw_section()?;
+        self.reader.read_exact(&mut self.checksums.manifest)?;
+        check(&self.checksums.manifest, &raw, || "manifest".into())?;
+
+        Ok((decode_manifest(&raw)?, Bytes::from(raw)))
+    }
+
+    pub(crate) fn data<D: ForgeModData + for<'a> Deserialize<'a>>(&mut self) -> Result<D, bincode::Error> {
+        if self.info.version == 0 {
+            let data: D = self.legacy_section()?;
+            self.check_entries(&data)?;
+            return Ok(data);
+        }
+
+        self.signature = self.section()?;
+        let meta = self.section()?;
+        let count: u32 = self.section()?;
+
+        let mut entries = Vec::new();
+        let mut blobs = HashMap::new();
+        let mut includes = 0;
+        for _ in 0..count {
+            let limit = limited(ENTRY_HEADER_LEN.saturating_add(self.limits.max_path_len as u64));
+            let header: EntryHeader = match self.info.version {
+                1 => limit.deserialize_from::<_, EntryHeaderV1>(&mut self.reader).map(Into::into),
+                _ => limit.deserialize_from(&mut self.reader),
+            }
+            .map_err(|e| size_limit_as(e, LimitError::PathLength { limit: self.limits.max_path_len }))?;
+
+            if header.kind == EntryKind::Include {
+                includes += 1;
+                self.limits.check_include_count(includes)?;
+            }
+            self.limits.check_path(&header.path)?;
+            self.check_entry_size(&entry_name(header.kind, &header.path), header.len)?;
+
+            let data = match &self.shared {
+                _ if header.shared => shared_blob(&blobs, &header)?,
+                Some(shared) if self.info.compression.codec == Codec::None => {
+                    if header.size != header.len {
+                        return Err(invalid_data(format!("entry {} has the wrong size", header.path)).into());
+                    }
+                    shared.slice(header.size)?
+                },
+                _ => {
+                    let mut stored = (&mut self.reader).take(header.size);
+                    let mut data = Vec::new();
+                    self.info
+                        .compression
+                        .codec
+                        .decompress(&mut stored)?
+                        .take(header.len)
+                        .read_to_end(&mut data)?;
+
+                    // the decoder stops at `len` bytes of output, the end of the stream can still be unread
+                    std::io::copy(&mut stored, &mut std::io::sink())?;
+                    if stored.limit() != 0 {
+                        return Err(invalid_data(format!("entry {} is truncated", header.path)).into());
+                    }
+                    Bytes::from(data)
+                },
+            };
+            if data.len() as u64 != header.len {
+                return Err(invalid_data(format!("entry {} is truncated", header.path)).into());
+            }
+            check(&header.sha256, &data, || entry_name(header.kind, &header.path))?;
+            self.checksums.entries.push(header.sha256);
+            blobs.entry(header.sha256).or_insert_with(|| data.clone());
+
+            entries.push(Entry {
+                kind: header.kind,
+                path: header.path,
+                data,
+                attrs: header.attrs,
+            });
+        }
+
+        // only the entries may hold on to the blobs, so owned ones are not copied again
+        drop(blobs);
+        D::from_entries(meta, entries)
+    }
+
+    /// Takes an entry of `len` bytes off the budget.
+    fn check_entry_size(&mut self, entry: &str, len: u64) -> Result<(), LimitError> {
+        if len > self.limits.max_entry_size {
+            return Err(LimitError::EntrySize {
+                entry: entry.into(),
+                limit: self.limits.max_entry_size,
+            });
+        }
+
+        self.budget = self.budget.checked_sub(len).ok_or(LimitError::TotalSize {
+            limit: self.limits.max_total_size,
+        })?;
+
+        Ok(())
+    }
+
+    /// Legacy packages are read in one go, their entries can only be checked afterwards.
+    fn check_entries<D: ForgeModData>(&self, data: &D) -> Result<(), LimitError> {
+        let entries = data.entries();
+
+        self.limits.check_include_count(entries.iter().filter(|e| e.kind == EntryKind::Include).count())?;
+        for entry in entries {
+            self.limits.check_path(entry.path.as_ref())?;
+            if entry.data.len() as u64 > self.limits.max_entry_size {
+                return Err(LimitError::EntrySize {
+                    entry: entry_name(entry.kind, &entry.path),
+                    limit: self.limits.max_entry_size,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the entry table of a packed forge mod, seeking straight to it.
+pub fn read_index<R: Read + Seek>(mut reader: R) -> Result<Vec<IndexEntry>, bincode::Error> {
+    Ok(seek_index(&mut reader)?.1)
+}
+
+/// Opens the include stored under `dest` in a packed forge mod.
+///
+/// Only the entry table and that one entry are read from `reader`, the entry is
+/// decompressed while the returned reader is consumed. Reading fails with a
+/// [`ChecksumError`] at the end of the entry if its digest does not match.
+pub fn open_include<'r, R: Read + Seek + 'r>(mut reader: R, dest: &str) -> Result<Box<dyn Read + 'r>, bincode::Error> {
+    let (start, index, info) = seek_index(&mut reader)?;
+
+    let entry = index
+        .into_iter()
+        .find(|e| e.kind == EntryKind::Include && e.path == dest)
+        .ok_or_else(|| invalid_data(format!("no include {} in package", dest)))?;
+
+    reader.seek(SeekFrom::Start(start + entry.offset))?;
+    Ok(Box::new(VerifyingReader {
+        inner: info.compression.codec.decompress(reader.take(entry.size))?.take(entry.len),
+        hasher: Sha256::new(),
+        expected: entry.sha256,
+        entry: entry.path,
+    }))
+}
+
+/// Returns where the container starts, its entry table and its header.
+fn seek_index<R: Read + Seek>(reader: &mut R) -> Result<(u64, Vec<IndexEntry>, ContainerInfo), bincode::Error> {
+    let start = reader.stream_position()?;
+
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    if !header.starts_with(&MAGIC) {
+        return Err(invalid_data("package has no entry table, unpack it instead".into()).into());
+    }
+    let info = parse_header(&header)?;
+
+    let mut index_offset = [0u8; 8];
+    reader.seek(SeekFrom::End(-8))?;
+    reader.read_exact(&mut index_offset)?;
+    reader.seek(SeekFrom::Start(start + u64::from_le_bytes(index_offset)))?;
+
+    let index = read_raw_section(reader, UnpackLimits::default().max_total_size)?;
+    let index = match info.version {
+        1 => bincode::deserialize::<Vec<IndexEntryV1>>(&index)?.into_iter().map(Into::into).collect(),
+        _ => bincode::deserialize(&index)?,
+    };
+    Ok((start, index, info))
+}
+
+/// Hands out a packed container a piece at a time, so sync and async writers share it:
+/// everything in front of the entries, then one compressed entry at a time, then the entry table.
+pub(crate) struct Packer<'a> {
+    head: Option<Vec<u8>>,
+    entries: std::iter::Zip<std::vec::IntoIter<EntryRef<'a>>, std::slice::Iter<'a, Digest>>,
+    compression: Compression,
+    /// Bytes handed out so far.
+    offset: u64,
+    index: Vec<IndexEntry>,
+    /// Offset and size of every blob written so far, by digest.
+    blobs: HashMap<Digest, (u64, u64)>,
+    done: bool,
+}
+
+/// What [`Packer::next_step`] hands out.
+pub(crate) enum Step<'a> {
+    /// Ready to be written.
+    Chunk(Vec<u8>),
+    /// An entry whose blob still has to be stored, see [`Packer::entry_chunk`].
+    Entry(PendingEntry<'a>),
+}
+
+pub(crate) struct PendingEntry<'a> {
+    pub(crate) entry: EntryRef<'a>,
+    sha256: &'a Digest,
+    /// Offset and size of the blob already written with the same digest.
+    shared: Option<(u64, u64)>,
+    compression: Compression,
+}
+
+impl PendingEntry<'_> {
+    /// The slow part of packing an entry, owned so it can be moved to another thread along with the data.
+    pub(crate) fn job(&self) -> BlobJob {
+        BlobJob {
+            name: entry_name(self.entry.kind, &self.entry.path),
+            sha256: *self.sha256,
+            shared: self.shared.is_some(),
+            compression: self.compression,
+        }
+    }
+}
+
+/// Checks the blob of an entry against its digest and compresses it, unless it was written already.
+pub(crate) struct BlobJob {
+    name: String,
+    sha256: Digest,
+    shared: bool,
+    compression: Compression,
+}
+
+impl BlobJob {
+    pub(crate) fn run(self, data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        check(&self.sha256, data, || self.name)?;
+
+        match self.shared {
+            true => Ok(Vec::new()),
+            false => self.compression.compress(data),
+        }
+    }
+}
+
+impl<'a> Packer<'a> {
+    pub(crate) fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, std::io::Error> {
+        match self.next_step()? {
+            Some(Step::Chunk(chunk)) => Ok(Some(chunk)),
+            Some(Step::Entry(pending)) => {
+                let stored = pending.job().run(pending.entry.data)?;
+                self.entry_chunk(pending, stored).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`Self::next_chunk`], but entries are handed out before their blob is compressed.
+    pub(crate) fn next_step(&mut self) -> Result<Option<Step<'a>>, std::io::Error> {
+        if let Some(head) = self.head.take() {
+            return Ok(Some(Step::Chunk(head)));
+        }
+
+        if let Some((entry, sha256)) = self.entries.next() {
+            return Ok(Some(Step::Entry(PendingEntry {
+                shared: self.blobs.get(sha256).copied(),
+                entry,
+                sha256,
+                compression: self.compression,
+            })));
+        }
+
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+
+        let mut tail = Vec::new();
+        write_section(&mut tail, &self.index)?;
+        tail.extend_from_slice(&self.offset.to_le_bytes());
+
+        Ok(Some(Step::Chunk(tail)))
+    }
+
+    /// The chunk of an entry, given what [`BlobJob::run`] made of its blob.
+    pub(crate) fn entry_chunk(&mut self, pending: PendingEntry<'a>, stored: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        let PendingEntry { entry, sha256, shared, .. } = pending;
+        let mut chunk = serialize(&EntryHeader {
+            kind: entry.kind,
+            path: entry.path.to_string(),
+            len: entry.data.len() as u64,
+            size: stored.len() as u64,
+            sha256: *sha256,
+            shared: shared.is_some(),
+            attrs: entry.attrs,
+        })
+        .map_err(std::io::Error::other)?;
+
+        let (blob_offset, size) = shared.unwrap_or((self.offset + chunk.len() as u64, stored.len() as u64));
+        self.blobs.insert(*sha256, (blob_offset, size));
+        self.index.push(IndexEntry {
+            kind: entry.kind,
+            path: entry.path.to_string(),
+            offset: blob_offset,
+            size,
+            len: entry.data.len() as u64,
+            sha256: *sha256,
+            attrs: entry.attrs,
+        });
+
+        chunk.extend(stored);
+        self.offset += chunk.len() as u64;
+        Ok(chunk)
+    }
+}
+
+/// Storage of the data of a forge mod inside a container.
+///
+/// Artifacts and includes are stored as separate entries so they can be read one
+/// at a time, everything else goes into the metadata section.
+pub trait ForgeModData: Sized {
+    /// Everything that is not stored as an entry.
+    type Meta: Serialize + for<'a> Deserialize<'a>;
+
+    fn meta(&self) -> Self::Meta;
+
+    /// Blobs to store as entries, in the order they are written.
+    fn entries(&self) -> Vec<EntryRef<'_>>;
+
+    /// Puts the data back together from entries in the order [`Self::entries`] listed them.
+    fn from_entries(meta: Self::Meta, entries: Vec<Entry>) -> Result<Self, bincode::Error>;
+}
 
 impl<
         Version: ManifestVersion + Serialize + for<'a> Deserialize<'a>,
@@ -45,15 +1213,163 @@ impl<
         Inner: ForgeModData + Serialize + for<'a> Deserialize<'a>,
     > ForgeMod<Version, Comp, Inner>
 {
+    /// Nothing but the mod itself goes into the output, packing the same mod always gives the same bytes.
     pub fn pack(&self) -> Result<Bytes, std::io::Error> {
-        let buf = serialize(&self).unwrap();
-        let buf = XzEncoder::new(buf, 9).finish()?;
+        let mut buf = Vec::new();
+        self.pack_to(&mut buf)?;
 
         Ok(Bytes::from(buf))
     }
 
+    /// Recomputes [`Self::checksums`], needed after changing the manifest or data of a built mod.
+    pub fn update_checksums(&mut self) {
+        self.raw_manifest = self.manifest_bytes().expect("manifests always serialize");
+        self.checksums = Checksums::compute(&self.raw_manifest, &self.data);
+    }
+
+    /// The manifest as it is packed and signed.
+    ///
+    /// That is the bytes read from the container as long as they still decode to [`Self::manifest`],
+    /// so fields added by later versions of the library survive a repack.
+    /// Once the manifest is changed it is encoded again.
+    pub(crate) fn manifest_bytes(&self) -> Result<Bytes, std::io::Error> {
+        let encoded = encode_manifest(&self.manifest)?;
+        if encoded == self.raw_manifest {
+            return Ok(self.raw_manifest.clone());
+        }
+
+        let stored: ForgeManifestSafe<Comp, Version> = decode_manifest(&self.raw_manifest)?;
+        match encode_manifest(&stored)? == encoded {
+            true => Ok(self.raw_manifest.clone()),
+            false => Ok(Bytes::from(encoded)),
+        }
+    }
+
+    /// SHA-256 of the canonical bytes of the mod: the bincode encoding of its format version and kind,
+    /// the stored manifest, the bincode encoding of its data and then the [`FileAttrs`] of the entries
+    /// if any are set. Compression, checksums and the signature are not part of it.
+    pub(crate) fn canonical_digest(&self) -> Digest {
+        let mut hasher = Sha256::new();
+        serialize_into(&mut hasher, &(self.format_version, &self.kind)).expect("mods always serialize");
+        let manifest = self.manifest_bytes().expect("manifests always serialize");
+        serialize_into(&mut hasher, serde_bytes::Bytes::new(&manifest)).expect("mods always serialize");
+        serialize_into(&mut hasher, &self.data).expect("mods always serialize");
+
+        // attributes are not part of the data, mods without any keep the digest they always had
+        let attrs: Vec<_> = self.data.entries().iter().map(|e| e.attrs).collect();
+        if attrs.iter().any(|a| *a != FileAttrs::default()) {
+            serialize_into(&mut hasher, &attrs).expect("attributes always serialize");
+        }
+        hasher.finalize().into()
+    }
+
+    /// Adds a detached signature over the canonical bytes, kept by [`Self::pack`].
+    pub fn sign(&mut self, key: &SigningKey) {
+        self.signature = Some(PackageSignature {
+            key: key.verifying_key(),
+            signature: key.sign(&self.canonical_digest()),
+        });
+    }
+
+    /// Checks that the mod was signed by `key` and did not change since.
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), SignatureError> {
+        let signature = self.signature.as_ref().ok_or(SignatureError::Unsigned)?;
+        if signature.key != *key {
+            return Err(SignatureError::WrongKey);
+        }
+
+        key.verify_strict(&self.canonical_digest(), &signature.signature)
+            .map_err(|_| SignatureError::Invalid)
+    }
+
+    /// Packs the mod straight into `writer`, only one compressed entry is held in memory at a time.
+    ///
+    /// Fails with a [`ChecksumError`] if anything changed since the checksums were recorded.
+    pub fn pack_to<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        let mut packer = self.packer()?;
+        while let Some(chunk) = packer.next_chunk()? {
+            writer.write_all(&chunk)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts packing the mod, everything in front of the entries is written right away.
+    pub(crate) fn packer(&self) -> Result<Packer<'_>, std::io::Error> {
+        let mut head = Vec::new();
+        write_header(&mut head, self.compression)?;
+        head.extend(serialize(&(self.format_version, &self.kind)).map_err(std::io::Error::other)?);
+
+        let manifest = self.manifest_bytes()?;
+        check(&self.checksums.manifest, &manifest, || "manifest".into())?;
+        write_raw_section(&mut head, &manifest)?;
+        head.extend_from_slice(&self.checksums.manifest);
+
+        let entries = self.data.entries();
+        if entries.len() != self.checksums.entries.len() {
+            return Err(invalid_data("entries changed since the checksums were recorded".into()));
+        }
+        write_section(&mut head, &self.signature)?;
+        write_section(&mut head, &self.data.meta())?;
+        write_section(&mut head, &(entries.len() as u32))?;
+
+        Ok(Packer {
+            offset: head.len() as u64,
+            head: Some(head),
+            index: Vec::with_capacity(entries.len()),
+            entries: entries.into_iter().zip(&self.checksums.entries),
+            compression: self.compression,
+            blobs: HashMap::new(),
+            done: false,
+        })
+    }
+
     pub fn from_bytes<'a, T: Into<&'a [u8]>>(bytes: T) -> Result<Self, bincode::Error> {
-        let contents = XzDecoder::new(bytes.into()).into_inner();
-        bincode::deserialize(contents)
+        Self::read_from(bytes.into())
+    }
+
+    /// Reads a packed mod from `reader`, decompressing it as it goes.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, bincode::Error> {
+        Self::read_from_with_limits(reader, &UnpackLimits::default())
+    }
+
+    /// Like [`Self::read_from`], for packages that need other limits than the default ones.
+    pub fn read_from_with_limits<R: Read>(reader: R, limits: &UnpackLimits) -> Result<Self, bincode::Error> {
+        Self::read_remaining(ContainerReader::open(reader, limits)?)
+    }
+
+    /// Reads a packed mod held in memory, see [`Blob`].
+    ///
+    /// Entries of packages packed with [`Codec::None`] point into `bytes` instead of being
+    /// copied, compressed entries are decompressed into buffers of their own.
+    pub fn from_shared(bytes: Bytes) -> Result<Self, bincode::Error> {
+        Self::read_remaining(ContainerReader::open_shared(bytes, &UnpackLimits::default())?)
+    }
+
+    /// Reads the rest of a mod whose header was already looked at.
+    pub(crate) fn read_remaining(mut container: ContainerReader) -> Result<Self, bincode::Error> {
+        let (manifest, raw_manifest) = container.stored_manifest()?;
+        let data = container.data()?;
+
+        let checksums = match container.info.version {
+            0 => Checksums::compute(&raw_manifest, &data),
+            _ => container.checksums,
+        };
+
+        Ok(Self {
+            format_version: container.generic.format_version,
+            kind: container.generic.kind.clone(),
+            manifest,
+            // legacy packages get repacked with the default
+            compression: match container.info.version {
+                0 => Compression::default(),
+                _ => container.info.compression,
+            },
+            data,
+            checksums,
+            signature: container.signature,
+            raw_manifest,
+            _marker: PhantomData,
+        })
     }
 }
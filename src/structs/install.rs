@@ -0,0 +1,902 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    path::{Component, Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    forgemod::{sha256, Blob, Digest, FileAttrs, ForgeMod},
+    v1::{data, manifest, ForgeModTypes, ManifestV1},
+};
+
+/// Folder of the game that mod and module artifacts are installed to.
+pub const PLUGINS_DIR: &str = "Plugins";
+
+/// Folder of the game that lib artifacts are installed to.
+pub const LIBS_DIR: &str = "Libs";
+
+/// The [`InstallState`] of a game folder, relative to it.
+pub const STATE_FILE: &str = "UserData/forge/installed.json";
+
+/// Where installs stage their files and back up what they replace, relative to the game folder.
+/// Left behind only if an install could not be rolled back.
+pub const TRANSACTION_DIR: &str = "UserData/forge/transaction";
+
+/// A file a mod puts into the game folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallFile<'a> {
+    /// `/` separated path below the game folder.
+    pub dest: String,
+    pub data: &'a [u8],
+    pub attrs: FileAttrs,
+}
+
+/// Why a mod could not be installed or uninstalled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallError {
+    /// `dest` is an absolute path.
+    Absolute { dest: String },
+    /// `dest` goes up with a `..` component.
+    Traversal { dest: String },
+    /// `dest` leads through a symlink pointing out of the game folder.
+    Escape { dest: String },
+    /// `dest` does not name a file.
+    Empty { dest: String },
+    /// `dest` is the same file as another file of the mod.
+    Duplicate { dest: String },
+    /// No receipt for a mod with this `_id`.
+    NotInstalled { id: String },
+    /// A `pre_exec` or `post_exec` program did not succeed.
+    Hook { program: String },
+    /// The `_id` of the mod is not a single path component, it names the transaction folder of its install.
+    InvalidId { id: String },
+    /// An earlier install of this `_id` was interrupted, its backups are still in the transaction folder.
+    Unfinished { id: String },
+    /// `path` of the mod `incoming` is already installed by `existing`, and the [`ConflictPolicy`] does not allow it.
+    Conflict {
+        path: String,
+        existing: String,
+        incoming: String,
+    },
+}
+
+impl Display for InstallError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Absolute { dest } => write!(f, "{} is an absolute path", dest),
+            Self::Traversal { dest } => write!(f, "{} leaves the game folder", dest),
+            Self::Escape { dest } => write!(f, "{} leads through a symlink out of the game folder", dest),
+            Self::Empty { dest } => write!(f, "{:?} is not a file path", dest),
+            Self::Duplicate { dest } => write!(f, "{} is installed more than once", dest),
+            Self::NotInstalled { id } => write!(f, "{} is not installed", id),
+            Self::Hook { program } => write!(f, "hook {} failed", program),
+            Self::InvalidId { id } => write!(f, "{:?} is not a valid mod id", id),
+            Self::Unfinished { id } => {
+                write!(f, "an earlier install of {} did not finish, see {}/{}", id, TRANSACTION_DIR, id)
+            },
+            Self::Conflict { path, existing, incoming } => {
+                write!(f, "{} of {} is already installed by {}", path, incoming, existing)
+            },
+        }
+    }
+}
+
+impl std::error::Error for InstallError {}
+
+impl From<InstallError> for std::io::Error {
+    fn from(err: InstallError) -> Self {
+        let kind = match err {
+            InstallError::NotInstalled { .. } => std::io::ErrorKind::NotFound,
+            InstallError::Conflict { .. } => std::io::ErrorKind::AlreadyExists,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// What an install wrote, kept in the [`InstallState`] until the mod is uninstalled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallReceipt {
+    pub id: String,
+    /// Modules have no version of their own.
+    pub version: Option<Version>,
+    pub files: Vec<ReceiptFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiptFile {
+    /// `/` separated path below the game folder.
+    pub path: String,
+    /// Digest of the contents as installed.
+    #[serde(with = "hex_digest")]
+    pub sha256: Digest,
+}
+
+/// Receipts of every mod installed into a game folder, stored as JSON in [`STATE_FILE`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallState {
+    /// Sorted by `id`.
+    pub receipts: Vec<InstallReceipt>,
+}
+
+impl InstallState {
+    /// Reads the state of `game_root`, empty if nothing was installed yet.
+    pub fn load<P: AsRef<Path>>(game_root: P) -> Result<Self, std::io::Error> {
+        match std::fs::read(game_root.as_ref().join(STATE_FILE)) {
+            Ok(state) => serde_json::from_slice(&state).map_err(std::io::Error::from),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces the state file as a whole, so a crash never leaves half of it behind.
+    fn save(&self, game_root: &Path) -> Result<(), std::io::Error> {
+        let path = game_root.join(STATE_FILE);
+        // safety: STATE_FILE has a parent, installs already created it as part of their transaction
+        std::fs::create_dir_all(path.parent().unwrap())?;
+
+        let tmp = path.with_extension("json.tmp");
+        let state = serde_json::to_vec_pretty(self)?;
+        let result = std::fs::write(&tmp, state).and_then(|_| std::fs::rename(&tmp, path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+
+        result
+    }
+
+    pub fn receipt(&self, id: &str) -> Option<&InstallReceipt> {
+        self.receipts.iter().find(|r| r.id == id)
+    }
+
+    fn take(&mut self, id: &str) -> Option<InstallReceipt> {
+        let at = self.receipts.iter().position(|r| r.id == id)?;
+        Some(self.receipts.remove(at))
+    }
+
+    /// Whether a mod still in the state installed a file at this path too.
+    fn used(&self, file: &ReceiptFile) -> bool {
+        self.receipts.iter().flat_map(|r| &r.files).any(|f| f.path == file.path)
+    }
+}
+
+/// A mod that has a file at some path, see [`Conflict`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOwner {
+    pub id: String,
+    pub version: Option<Version>,
+    pub sha256: Digest,
+}
+
+/// Two mods put a file at the same path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// `/` separated path below the game folder.
+    pub path: String,
+    /// The mod installed, or listed, first.
+    pub existing: FileOwner,
+    pub incoming: FileOwner,
+}
+
+/// What an install does with a file another installed mod already has.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fails with [`InstallError::Conflict`].
+    Fail,
+    /// The file of the mod with the higher version is kept, the other one is not written.
+    /// Modules have no version and always lose, ties go to the installed file.
+    HigherVersion,
+    /// Allowed if both files are the same, fails with [`InstallError::Conflict`] otherwise.
+    #[default]
+    SameHash,
+}
+
+impl ConflictPolicy {
+    /// Whether the incoming file is written, or why the install cannot go on.
+    fn settle(self, conflict: &Conflict) -> Result<bool, InstallError> {
+        let err = || InstallError::Conflict {
+            path: conflict.path.clone(),
+            existing: conflict.existing.id.clone(),
+            incoming: conflict.incoming.id.clone(),
+        };
+
+        match self {
+            Self::Fail => Err(err()),
+            Self::HigherVersion => Ok(conflict.incoming.version > conflict.existing.version),
+            Self::SameHash if conflict.incoming.sha256 == conflict.existing.sha256 => Ok(true),
+            Self::SameHash => Err(err()),
+        }
+    }
+}
+
+/// Files the mods about to be installed into `game_root` share with installed mods or with each other.
+///
+/// Mods are compared in order, against the receipts as they will be once the ones before are
+/// installed. A mod replacing an installed version of itself is not a conflict.
+pub fn find_conflicts<P: AsRef<Path>, B: Blob>(
+    game_root: P,
+    mods: &[ForgeModTypes<B>],
+) -> Result<Vec<Conflict>, std::io::Error> {
+    let root = game_root.as_ref();
+    let mut state = InstallState::load(root)?;
+
+    let mut conflicts = Vec::new();
+    for forgemod in mods {
+        let prepared = forgemod.prepare(root, state)?;
+        conflicts.extend(prepared.conflicts);
+
+        state = prepared.state;
+        let at = state.receipts.partition_point(|r| r.id < prepared.receipt.id);
+        state.receipts.insert(at, prepared.receipt);
+    }
+
+    Ok(conflicts)
+}
+
+/// What [`uninstall`] did with the files of a receipt.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UninstallReport {
+    pub removed: Vec<String>,
+    /// Changed since the install or still used by another mod, left in place.
+    pub kept: Vec<String>,
+}
+
+/// Removes the files the mod with this `_id` installed into `game_root` and its receipt.
+///
+/// Only files that are still as installed are removed. Files changed since then are kept,
+/// as are files another installed mod put at the same path. Files already gone are skipped.
+pub fn uninstall<P: AsRef<Path>>(game_root: P, id: &str) -> Result<UninstallReport, std::io::Error> {
+    let root = game_root.as_ref();
+    let mut state = InstallState::load(root)?;
+    let receipt = state.take(id).ok_or_else(|| InstallError::NotInstalled { id: id.into() })?;
+
+    let mut report = UninstallReport::default();
+    remove_unchanged(root, &receipt.files, &state, &mut report, |path| std::fs::remove_file(path))?;
+    state.save(root)?;
+
+    Ok(report)
+}
+
+fn remove_unchanged(
+    root: &Path,
+    files: &[ReceiptFile],
+    state: &InstallState,
+    report: &mut UninstallReport,
+    mut remove: impl FnMut(&Path) -> Result<(), std::io::Error>,
+) -> Result<(), std::io::Error> {
+    for file in files {
+        // receipts are read back from disk, they get no more trust than packages
+        let (path, _) = resolve(root, &file.path)?;
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        if sha256(&data) != file.sha256 || state.used(file) {
+            report.kept.push(file.path.clone());
+            continue;
+        }
+        remove(&path)?;
+        report.removed.push(file.path.clone());
+    }
+
+    Ok(())
+}
+
+/// When an [`InstallHooks`] program runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    /// `pre_exec`, before any file is put in place.
+    Pre,
+    /// `post_exec`, once every file is in place but before the install is recorded.
+    Post,
+}
+
+/// Runs the `pre_exec` and `post_exec` programs of a mod, see [`ForgeModTypes::install_with`].
+pub trait InstallHooks {
+    /// `program` is already resolved below `game_root`, an error rolls the install back.
+    fn run(&mut self, stage: HookStage, program: &Path, game_root: &Path) -> Result<(), std::io::Error>;
+}
+
+/// Runs hooks as processes in the game folder, failing on a non-zero exit code.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandHooks;
+
+impl InstallHooks for CommandHooks {
+    fn run(&mut self, _: HookStage, program: &Path, game_root: &Path) -> Result<(), std::io::Error> {
+        let status = std::process::Command::new(program).current_dir(game_root).status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(InstallError::Hook {
+                program: program.display().to_string(),
+            }
+            .into()),
+        }
+    }
+}
+
+impl<B: Blob> ForgeModTypes<B> {
+    /// Files the mod puts into the game folder, its artifact first.
+    ///
+    /// Artifacts go to [`PLUGINS_DIR`], or [`LIBS_DIR`] for libs, named after the `_id`.
+    /// A parent brings its required and suggested modules, other modules are installed on their own.
+    pub fn install_files(&self) -> Vec<InstallFile<'_>> {
+        match self {
+            Self::Mod(m) => artifact_files(PLUGINS_DIR, &m.manifest._id, &m.data.artifact_data, &m.data.includes_data),
+            Self::Parent(m) => selected_modules(m).flat_map(module_files).collect(),
+            Self::Module(m) => module_files(m),
+            Self::Lib(m) => artifact_files(LIBS_DIR, &m.manifest._id, &m.data.artifact_data, &m.data.includes_data),
+        }
+    }
+
+    /// Writes the files of the mod below `game_root` with [`CommandHooks`] and the default
+    /// [`ConflictPolicy`], see [`Self::install_with`].
+    pub fn install<P: AsRef<Path>>(&self, game_root: P) -> Result<InstallReceipt, std::io::Error> {
+        self.install_with(game_root, ConflictPolicy::default(), &mut CommandHooks)
+    }
+
+    /// Writes the files of the mod below `game_root`, see [`Self::install_files`], and
+    /// records them in the [`InstallState`] so [`uninstall`] can take them out again.
+    ///
+    /// Every path is checked before anything is written. A `dest` that is absolute, goes
+    /// up with `..` or leads through a symlink out of `game_root` fails with an [`InstallError`].
+    /// Installing over an older version removes the files it no longer has, unless they were changed.
+    /// Files other installed mods have as well are settled by `policy` before anything is written.
+    ///
+    /// Files are staged in [`TRANSACTION_DIR`] first and whatever they replace is backed up there.
+    /// If a step fails, `pre_exec` and `post_exec` hooks included, everything done so far is
+    /// undone and the game folder is left as it was.
+    pub fn install_with<P: AsRef<Path>>(
+        &self,
+        game_root: P,
+        policy: ConflictPolicy,
+        hooks: &mut impl InstallHooks,
+    ) -> Result<InstallReceipt, std::io::Error> {
+        let root = game_root.as_ref();
+        let mut prepared = self.prepare(root, InstallState::load(root)?)?;
+        prepared.settle(policy)?;
+        let Prepared {
+            files,
+            targets,
+            pre_exec,
+            post_exec,
+            mut state,
+            stale,
+            receipt,
+            ..
+        } = prepared;
+
+        let mut transaction = Transaction::new(root, &receipt.id)?;
+        let result = (|| {
+            transaction.begin()?;
+            for program in &pre_exec {
+                hooks.run(HookStage::Pre, program, root)?;
+            }
+
+            let staged = files
+                .iter()
+                .map(|file| transaction.stage(file))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (staged, target) in staged.into_iter().zip(&targets) {
+                transaction.commit(staged, target)?;
+            }
+
+            remove_unchanged(root, &stale, &state, &mut UninstallReport::default(), |path| transaction.remove(path))?;
+
+            for program in &post_exec {
+                hooks.run(HookStage::Post, program, root)?;
+            }
+
+            let at = state.receipts.partition_point(|r| r.id < receipt.id);
+            state.receipts.insert(at, receipt.clone());
+            transaction.checkpoint()?;
+            state.save(root)
+        })();
+
+        match result {
+            Ok(()) => transaction.finish()?,
+            Err(e) => {
+                if let Err(rollback) = transaction.rollback() {
+                    return Err(std::io::Error::new(e.kind(), format!("{}, rolling back failed as well: {}", e, rollback)));
+                }
+                return Err(e);
+            },
+        }
+
+        Ok(receipt)
+    }
+
+    /// Checks everything an install needs and works out what it is going to do
+    /// to a game folder in `state`, nothing in the game folder is changed.
+    fn prepare(&self, root: &Path, mut state: InstallState) -> Result<Prepared<'_>, std::io::Error> {
+        let mut components = Path::new(self.id()).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(id)), None) if id == self.id() => {},
+            _ => return Err(InstallError::InvalidId { id: self.id().into() }.into()),
+        }
+
+        let files = self.install_files();
+        let (pre_exec, post_exec) = self.hooks();
+
+        let (targets, paths): (Vec<_>, Vec<_>) = files
+            .iter()
+            .map(|file| resolve(root, &file.dest))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        let mut seen = HashSet::new();
+        if let Some((file, _)) = files.iter().zip(&paths).find(|(_, path)| !seen.insert(path.as_str())) {
+            return Err(InstallError::Duplicate { dest: file.dest.clone() }.into());
+        }
+        let pre_exec = pre_exec.into_iter().map(|p| hook_path(root, p)).collect::<Result<_, _>>()?;
+        let post_exec = post_exec.into_iter().map(|p| hook_path(root, p)).collect::<Result<_, _>>()?;
+
+        let receipt = InstallReceipt {
+            id: self.id().into(),
+            version: self.version().cloned(),
+            files: files
+                .iter()
+                .zip(paths)
+                .map(|(file, path)| ReceiptFile {
+                    path,
+                    sha256: sha256(file.data),
+                })
+                .collect(),
+        };
+
+        let stale = match state.take(&receipt.id) {
+            Some(old) => old.files.into_iter().filter(|f| !receipt.files.iter().any(|n| n.path == f.path)).collect(),
+            None => Vec::new(),
+        };
+
+        let mut conflicts = Vec::new();
+        for file in &receipt.files {
+            for other in &state.receipts {
+                if let Some(existing) = other.files.iter().find(|f| f.path == file.path) {
+                    conflicts.push(Conflict {
+                        path: file.path.clone(),
+                        existing: FileOwner {
+                            id: other.id.clone(),
+                            version: other.version.clone(),
+                            sha256: existing.sha256,
+                        },
+                        incoming: FileOwner {
+                            id: receipt.id.clone(),
+                            version: receipt.version.clone(),
+                            sha256: file.sha256,
+                        },
+                    });
+                }
+            }
+        }
+
+        Ok(Prepared {
+            files,
+            targets,
+            pre_exec,
+            post_exec,
+            state,
+            stale,
+            conflicts,
+            receipt,
+        })
+    }
+
+    /// `pre_exec` and `post_exec` programs, a parent runs those of its modules after its own.
+    fn hooks(&self) -> (Vec<&Path>, Vec<&Path>) {
+        let mut hooks = vec![match self {
+            Self::Mod(m) => (&m.manifest.inner.pre_exec, &m.manifest.inner.post_exec),
+            Self::Parent(m) => (&m.manifest.inner.pre_exec, &m.manifest.inner.post_exec),
+            Self::Module(m) => (&m.manifest.inner.pre_exec, &m.manifest.inner.post_exec),
+            Self::Lib(m) => (&m.manifest.inner.pre_exec, &m.manifest.inner.post_exec),
+        }];
+        if let Self::Parent(m) = self {
+            hooks.extend(selected_modules(m).map(|m| (&m.manifest.inner.pre_exec, &m.manifest.inner.post_exec)));
+        }
+
+        (
+            hooks.iter().filter_map(|(pre, _)| pre.as_deref()).collect(),
+            hooks.iter().filter_map(|(_, post)| post.as_deref()).collect(),
+        )
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            Self::Mod(m) => &m.manifest._id,
+            Self::Parent(m) => &m.manifest._id,
+            Self::Module(m) => &m.manifest._id,
+            Self::Lib(m) => &m.manifest._id,
+        }
+    }
+
+    fn version(&self) -> Option<&Version> {
+        match self {
+            Self::Mod(m) => Some(&m.manifest.inner.version),
+            Self::Parent(m) => Some(&m.manifest.inner.version),
+            Self::Module(_) => None,
+            Self::Lib(m) => Some(&m.manifest.inner.version),
+        }
+    }
+}
+
+fn selected_modules<B: Blob>(
+    m: &ForgeMod<ManifestV1, manifest::Parent, data::Parent<B>>,
+) -> impl Iterator<Item = &ForgeMod<ManifestV1, manifest::Module, data::Module<B>>> {
+    m.data.modules.iter().filter(|module| module.data.required || module.data.suggested)
+}
+
+fn module_files<B: Blob>(m: &ForgeMod<ManifestV1, manifest::Module, data::Module<B>>) -> Vec<InstallFile<'_>> {
+    artifact_files(PLUGINS_DIR, &m.manifest._id, &m.data.artifact_data, &m.data.includes_data)
+}
+
+fn artifact_files<'a, B: Blob>(
+    dir: &str,
+    id: &str,
+    artifact_data: &'a B,
+    includes_data: &'a [data::IncludeData<B>],
+) -> Vec<InstallFile<'a>> {
+    let artifact = InstallFile {
+        dest: format!("{}/{}.dll", dir, id),
+        data: artifact_data.as_ref(),
+        attrs: FileAttrs::default(),
+    };
+
+    std::iter::once(artifact)
+        .chain(includes_data.iter().map(|i| InstallFile {
+            dest: i.dest.clone(),
+            data: i.data.as_ref(),
+            attrs: i.attrs,
+        }))
+        .collect()
+}
+
+/// What an install is going to do, see [`ForgeModTypes::prepare`].
+struct Prepared<'a> {
+    files: Vec<InstallFile<'a>>,
+    /// Where each of the `files` goes.
+    targets: Vec<PathBuf>,
+    pre_exec: Vec<PathBuf>,
+    post_exec: Vec<PathBuf>,
+    /// The state without the receipt of the version installed before.
+    state: InstallState,
+    /// Files of the version installed before that the new one does not have.
+    stale: Vec<ReceiptFile>,
+    /// Files of the mod that other mods in `state` have too.
+    conflicts: Vec<Conflict>,
+    receipt: InstallReceipt,
+}
+
+impl Prepared<'_> {
+    /// Settles the conflicts with `policy`, the files that lose are not written or recorded.
+    /// Files that win over a different one are taken off the receipt of the mod that had it.
+    fn settle(&mut self, policy: ConflictPolicy) -> Result<(), InstallError> {
+        let mut lost = HashSet::new();
+        for conflict in &self.conflicts {
+            match policy.settle(conflict)? {
+                false => {
+                    lost.insert(conflict.path.clone());
+                },
+                true if conflict.incoming.sha256 != conflict.existing.sha256 => {
+                    if let Some(existing) = self.state.receipts.iter_mut().find(|r| r.id == conflict.existing.id) {
+                        existing.files.retain(|f| f.path != conflict.path);
+                    }
+                },
+                true => {},
+            }
+        }
+
+        let keep: Vec<_> = self.receipt.files.iter().map(|f| !lost.contains(&f.path)).collect();
+        let mut kept = keep.iter();
+        self.files.retain(|_| *kept.next().unwrap());
+        let mut kept = keep.iter();
+        self.targets.retain(|_| *kept.next().unwrap());
+        let mut kept = keep.iter();
+        self.receipt.files.retain(|_| *kept.next().unwrap());
+
+        Ok(())
+    }
+}
+
+/// What installing a mod would do to a game folder, made by [`plan_install`].
+///
+/// Paths are `/` separated and below the game folder.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstallPlan {
+    pub id: String,
+    pub version: Option<Version>,
+    /// Files that are not there yet.
+    pub create: Vec<String>,
+    /// Files that are there and get replaced.
+    pub overwrite: Vec<String>,
+    /// Files of the mod that other installed mods have as well, the ones that lose
+    /// under the [`ConflictPolicy`] are not in `create`, `overwrite` or `bytes`.
+    pub conflicts: Vec<Conflict>,
+    /// Files of the version installed before that go away.
+    pub remove: Vec<String>,
+    /// Size of all files written.
+    pub bytes: u64,
+    /// Programs run, in order.
+    pub hooks: Vec<(HookStage, PathBuf)>,
+}
+
+/// Works out what [`ForgeModTypes::install_with`] would do to `game_root` with `policy`, without changing anything.
+///
+/// Fails the same way the install would before writing anything, for an unsafe `dest` or a conflict for instance.
+pub fn plan_install<P: AsRef<Path>, B: Blob>(
+    game_root: P,
+    forgemod: &ForgeModTypes<B>,
+    policy: ConflictPolicy,
+) -> Result<InstallPlan, std::io::Error> {
+    let root = game_root.as_ref();
+    let mut prepared = forgemod.prepare(root, InstallState::load(root)?)?;
+    prepared.settle(policy)?;
+
+    let mut plan = InstallPlan {
+        id: prepared.receipt.id.clone(),
+        version: prepared.receipt.version.clone(),
+        ..Default::default()
+    };
+    for ((file, target), receipt) in prepared.files.iter().zip(&prepared.targets).zip(&prepared.receipt.files) {
+        match existing(target)? {
+            true => plan.overwrite.push(receipt.path.clone()),
+            false => plan.create.push(receipt.path.clone()),
+        }
+        plan.bytes += file.data.len() as u64;
+    }
+
+    let mut report = UninstallReport::default();
+    remove_unchanged(root, &prepared.stale, &prepared.state, &mut report, |_| Ok(()))?;
+    plan.remove = report.removed;
+    plan.conflicts = prepared.conflicts;
+
+    plan.hooks = (prepared.pre_exec.into_iter().map(|p| (HookStage::Pre, p)))
+        .chain(prepared.post_exec.into_iter().map(|p| (HookStage::Post, p)))
+        .collect();
+
+    Ok(plan)
+}
+
+/// Whether there is a file at `target` to replace, directories never are.
+fn existing(target: &Path) -> Result<bool, std::io::Error> {
+    match std::fs::symlink_metadata(target) {
+        Ok(m) if m.is_dir() => Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} is a directory", target.display()),
+        )),
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Hooks are programs in the game folder, held to the same rules as the files of a mod.
+fn hook_path(root: &Path, program: &Path) -> Result<PathBuf, InstallError> {
+    let program = program.to_str().ok_or_else(|| InstallError::Empty {
+        dest: program.display().to_string(),
+    })?;
+
+    Ok(resolve(root, program)?.0)
+}
+
+/// A change made to the game folder by an install.
+enum Step {
+    /// The transaction folder was created.
+    Began,
+    CreatedDir(PathBuf),
+    Created(PathBuf),
+    /// `path` was there before, it was moved to `backup`.
+    Replaced { path: PathBuf, backup: PathBuf },
+}
+
+/// Stages the files of one install and journals every change it makes to the game
+/// folder, so they can be undone in reverse order.
+struct Transaction<'a> {
+    root: &'a Path,
+    /// Below [`TRANSACTION_DIR`], on the same file system as the game so moves are renames.
+    dir: PathBuf,
+    journal: Vec<Step>,
+    /// Files staged or backed up so far, names them in `dir`.
+    files: usize,
+}
+
+impl<'a> Transaction<'a> {
+    fn new(root: &'a Path, id: &str) -> Result<Self, std::io::Error> {
+        let dir = root.join(TRANSACTION_DIR).join(id);
+        if dir.exists() {
+            return Err(InstallError::Unfinished { id: id.into() }.into());
+        }
+
+        Ok(Self {
+            root,
+            dir,
+            journal: Vec::new(),
+            files: 0,
+        })
+    }
+
+    /// Creates the transaction folder, and the folders above it that are missing, as the first steps.
+    fn begin(&mut self) -> Result<(), std::io::Error> {
+        // safety: the transaction folder is below the root
+        let parent = self.dir.parent().unwrap().to_path_buf();
+        self.create_dirs(&parent)?;
+
+        self.checkpoint()?;
+        std::fs::create_dir(&self.dir)?;
+        self.journal.push(Step::Began);
+        std::fs::create_dir(self.dir.join("staged"))?;
+        std::fs::create_dir(self.dir.join("backup"))
+    }
+
+    /// Creates `dir` and every missing folder above it, one step each.
+    fn create_dirs(&mut self, dir: &Path) -> Result<(), std::io::Error> {
+        let missing: Vec<_> = dir
+            .ancestors()
+            .take_while(|dir| *dir != self.root && !dir.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in missing.into_iter().rev() {
+            self.checkpoint()?;
+            std::fs::create_dir(&dir)?;
+            self.journal.push(Step::CreatedDir(dir));
+        }
+
+        Ok(())
+    }
+
+    fn next(&mut self, kind: &str) -> PathBuf {
+        self.files += 1;
+        self.dir.join(kind).join(self.files.to_string())
+    }
+
+    /// Writes a file to the staging folder, nothing in the game folder changes yet.
+    fn stage(&mut self, file: &InstallFile<'_>) -> Result<PathBuf, std::io::Error> {
+        self.checkpoint()?;
+        let staged = self.next("staged");
+        std::fs::write(&staged, file.data)?;
+        file.attrs.apply(&staged)?;
+
+        Ok(staged)
+    }
+
+    /// Moves a staged file to `target`, backing up what was there.
+    fn commit(&mut self, staged: PathBuf, target: &Path) -> Result<(), std::io::Error> {
+        // safety: targets are below the root
+        self.create_dirs(target.parent().unwrap())?;
+
+        if existing(target)? {
+            self.remove(target)?;
+        }
+
+        self.checkpoint()?;
+        std::fs::rename(staged, target)?;
+        self.journal.push(Step::Created(target.to_path_buf()));
+
+        Ok(())
+    }
+
+    /// Moves a file of the game folder to the backups.
+    fn remove(&mut self, path: &Path) -> Result<(), std::io::Error> {
+        self.checkpoint()?;
+        let backup = self.next("backup");
+        std::fs::rename(path, &backup)?;
+        self.journal.push(Step::Replaced {
+            path: path.to_path_buf(),
+            backup,
+        });
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), std::io::Error> {
+        std::fs::remove_dir_all(&self.dir)
+    }
+
+    /// Undoes every change in reverse order. If that fails too, the transaction
+    /// folder is kept with the backups that could not be put back.
+    fn rollback(mut self) -> Result<(), std::io::Error> {
+        while let Some(step) = self.journal.pop() {
+            match step {
+                Step::Began => std::fs::remove_dir_all(&self.dir)?,
+                Step::CreatedDir(dir) => std::fs::remove_dir(dir)?,
+                Step::Created(path) => std::fs::remove_file(path)?,
+                Step::Replaced { path, backup } => std::fs::rename(backup, path)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails where a test asked to, see [`FAIL_AFTER`].
+    fn checkpoint(&self) -> Result<(), std::io::Error> {
+        #[cfg(test)]
+        FAIL_AFTER.with(|steps| match steps.get() {
+            Some(0) => Err(std::io::Error::other("injected failure")),
+            Some(n) => {
+                steps.set(Some(n - 1));
+                Ok(())
+            },
+            None => Ok(()),
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of steps an install gets through on this thread before the next one fails.
+    pub(crate) static FAIL_AFTER: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+/// Where `dest` goes below `root`, as long as it stays there, and `dest` in the
+/// `/` separated form receipts use.
+///
+/// Both `/` and `\` separate components, packages are built on any platform.
+pub(crate) fn resolve(root: &Path, dest: &str) -> Result<(PathBuf, String), InstallError> {
+    let err = || dest.to_string();
+    let bytes = dest.as_bytes();
+    if bytes.starts_with(b"/") || bytes.starts_with(b"\\") || bytes.get(1) == Some(&b':') {
+        return Err(InstallError::Absolute { dest: err() });
+    }
+
+    let mut target = root.to_path_buf();
+    let mut parts = Vec::new();
+    for part in dest.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return Err(InstallError::Traversal { dest: err() }),
+            part => {
+                target.push(part);
+                parts.push(part);
+            },
+        }
+
+        // a symlink here is followed by everything below it
+        let is_symlink = std::fs::symlink_metadata(&target).is_ok_and(|m| m.file_type().is_symlink());
+        if is_symlink && !stays_inside(root, &target) {
+            return Err(InstallError::Escape { dest: err() });
+        }
+    }
+
+    match parts.is_empty() {
+        true => Err(InstallError::Empty { dest: err() }),
+        false => Ok((target, parts.join("/"))),
+    }
+}
+
+fn stays_inside(root: &Path, path: &Path) -> bool {
+    match (std::fs::canonicalize(root), std::fs::canonicalize(path)) {
+        (Ok(root), Ok(path)) => path.starts_with(root),
+        // dangling links cannot be followed safely
+        _ => false,
+    }
+}
+
+/// `serde(with)` module writing digests as lowercase hex, to keep the state file readable.
+mod hex_digest {
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+    use super::Digest;
+
+    pub fn serialize<S: Serializer>(digest: &Digest, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&digest.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Digest, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        let mut digest = Digest::default();
+        if hex.len() != digest.len() * 2 || !hex.is_ascii() {
+            return Err(D::Error::custom("digest is not 64 hex digits"));
+        }
+        for (byte, pair) in digest.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            // safety: checked to be ASCII
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).map_err(D::Error::custom)?;
+        }
+
+        Ok(digest)
+    }
+}
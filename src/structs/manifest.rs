@@ -59,6 +59,18 @@ impl<Inner: ManifestComponent, Version: ManifestVersion> From<ForgeManifest<Inne
     }
 }
 
+impl<Inner: ManifestComponent, Version: ManifestVersion> From<ForgeManifestSafe<Inner, Version>> for ForgeManifest<Inner, Version> {
+    fn from(manifest: ForgeManifestSafe<Inner, Version>) -> Self {
+        Self {
+            _id: manifest._id,
+            manifest_version: manifest.manifest_version,
+            _type: manifest._type,
+            inner: manifest.inner,
+            _marker: PhantomData,
+        }
+    }
+}
+
 /// Marker trait for forge manifest components.
 pub trait ManifestComponent {}
 
@@ -1,3 +1,8 @@
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod delta;
 pub mod forgemod;
+pub mod install;
 pub mod manifest;
+pub mod unpack;
 pub mod v1;
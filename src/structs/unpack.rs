@@ -0,0 +1,125 @@
+use std::io::Read;
+
+use super::{
+    forgemod::{local_error, surface_error, Blob, ContainerReader, LimitError, UnpackLimits},
+    manifest::ForgeManifestGeneric,
+    v1,
+};
+
+/// Newest format version, packages and manifests of older ones are migrated to it.
+pub const LATEST_FORMAT_VERSION: u32 = 1;
+
+/// A forge mod in the newest in-memory model, whatever format version it was packed with.
+pub type AnyForgeMod<B = Vec<u8>> = v1::ForgeModTypes<B>;
+
+/// A forge manifest in the newest in-memory model, whatever version it was written with.
+pub type AnyForgeManifest = v1::ForgeManifestTypes;
+
+/// A forge mod as read by the reader of its own format version.
+#[derive(Debug)]
+pub enum VersionedForgeMod<B: Blob = Vec<u8>> {
+    V1(v1::ForgeModTypes<B>),
+}
+
+impl<B: Blob> VersionedForgeMod<B> {
+    pub fn format_version(&self) -> u32 {
+        match self {
+            Self::V1(_) => 1,
+        }
+    }
+
+    /// Upgrades the mod one format version at a time until it is in the newest model.
+    pub fn migrate(self) -> AnyForgeMod<B> {
+        match self {
+            Self::V1(m) => m,
+        }
+    }
+}
+
+/// A forge manifest as read by the parser of its own manifest version.
+#[derive(Debug)]
+pub enum VersionedForgeManifest {
+    V1(v1::ForgeManifestTypes),
+}
+
+impl VersionedForgeManifest {
+    pub fn manifest_version(&self) -> u32 {
+        match self {
+            Self::V1(_) => 1,
+        }
+    }
+
+    /// Upgrades the manifest one version at a time until it is in the newest model.
+    pub fn migrate(self) -> AnyForgeManifest {
+        match self {
+            Self::V1(m) => m,
+        }
+    }
+}
+
+/// Unpacks a forge mod of any supported format version into the newest model.
+pub fn unpack_forgemod<'a, T: Into<&'a [u8]>>(data: T) -> Result<AnyForgeMod, Box<dyn std::error::Error>> {
+    unpack_forgemod_from(data.into())
+}
+
+/// Streaming version of [`unpack_forgemod`].
+pub fn unpack_forgemod_from<R: Read>(reader: R) -> Result<AnyForgeMod, Box<dyn std::error::Error>> {
+    unpack_forgemod_with_limits(reader, &UnpackLimits::default())
+}
+
+/// [`unpack_forgemod_from`] with other limits than the default ones.
+pub fn unpack_forgemod_with_limits<R: Read>(
+    reader: R,
+    limits: &UnpackLimits,
+) -> Result<AnyForgeMod, Box<dyn std::error::Error>> {
+    Ok(read_versioned_forgemod(reader, limits)?.migrate())
+}
+
+/// Reads a forge mod with the reader of its format version, without migrating it.
+pub fn read_versioned_forgemod<R: Read>(
+    reader: R,
+    limits: &UnpackLimits,
+) -> Result<VersionedForgeMod, Box<dyn std::error::Error>> {
+    read_versioned(reader, limits).map_err(local_error)
+}
+
+pub(crate) fn read_versioned<R: Read>(
+    reader: R,
+    limits: &UnpackLimits,
+) -> Result<VersionedForgeMod, Box<dyn std::error::Error + Send + Sync>> {
+    let container = ContainerReader::open(reader, limits).map_err(surface_error)?;
+
+    match container.generic.format_version {
+        1 => Ok(VersionedForgeMod::V1(v1::unpack_v1_container(container)?)),
+        v => Err(format!("unsupported format version {}", v).into()),
+    }
+}
+
+/// Parses a forge manifest of any supported manifest version into the newest model.
+pub fn parse_forgemanifest<'a, T: Into<&'a [u8]>>(data: T) -> Result<AnyForgeManifest, Box<dyn std::error::Error>> {
+    parse_forgemanifest_with_limits(data, &UnpackLimits::default())
+}
+
+/// [`parse_forgemanifest`] with other limits than the default ones.
+pub fn parse_forgemanifest_with_limits<'a, T: Into<&'a [u8]>>(
+    data: T,
+    limits: &UnpackLimits,
+) -> Result<AnyForgeManifest, Box<dyn std::error::Error>> {
+    Ok(read_versioned_forgemanifest(data, limits)?.migrate())
+}
+
+/// Parses a forge manifest with the parser of its manifest version, without migrating it.
+pub fn read_versioned_forgemanifest<'a, T: Into<&'a [u8]>>(
+    data: T,
+    limits: &UnpackLimits,
+) -> Result<VersionedForgeManifest, Box<dyn std::error::Error>> {
+    let data = data.into();
+    if data.len() as u64 > limits.max_total_size {
+        return Err(LimitError::TotalSize { limit: limits.max_total_size }.into());
+    }
+
+    match ForgeManifestGeneric::from_bytes(data)?.manifest_version {
+        1 => Ok(VersionedForgeManifest::V1(v1::parse_v1_forgemanifest_with_limits(data, limits)?)),
+        v => Err(format!("unsupported manifest version {}", v).into()),
+    }
+}
@@ -1,19 +1,25 @@
 #![allow(dead_code)]
 
 use std::{
+    collections::HashSet,
+    io::Read,
     marker::PhantomData,
     path::PathBuf,
     str::FromStr, fmt::Display,
     fmt::Formatter
 };
 
+use bytes::Bytes;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 
 use crate::build_manifest_builder;
 
 use super::{
-    forgemod::{ForgeMod, ForgeModData, ForgeModGeneric},
+    forgemod::{
+        blob, decode_manifest, encode_manifest, local_error, surface_error, Blob, Checksums, Compression, ContainerReader, LimitError, UnpackLimits, Entry, EntryKind, EntryRef,
+        FileAttrs, ForgeMod, ForgeModData, PackageSignature,
+    },
     manifest::*,
 };
 
@@ -197,13 +203,13 @@ enum ManifestTypes {
     Lib,
 }
 
-impl ToString for ManifestTypes {
-    fn to_string(&self) -> String {
+impl Display for ManifestTypes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Mod => "mod".to_string(),
-            Self::ModuleParent => "module_parent".to_string(),
-            Self::Module => "module".to_string(),
-            Self::Lib => "lib".to_string(),
+            Self::Mod => write!(f, "mod"),
+            Self::ModuleParent => write!(f, "module_parent"),
+            Self::Module => write!(f, "module"),
+            Self::Lib => write!(f, "lib"),
         }
     }
 }
@@ -352,7 +358,7 @@ impl ManifestBuilder<manifest::Module> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct IncludeBuilder {
     pub(self) _inners: Vec<manifest::Include>,
 }
@@ -373,7 +379,7 @@ impl IncludeBuilder {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct DependencyBuilder {
     pub(self) _inners: Vec<manifest::Dependency>,
 }
@@ -403,44 +409,255 @@ impl DependencyBuilder {
 pub mod data {
     use super::*;
 
+    /// Artifact and include bytes are held in a [`Blob`], owned `Vec<u8>`s unless read
+    /// with [`unpack_v1_forgemod_shared`].
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-    pub struct IncludeData {
+    #[serde(bound = "")]
+    pub struct IncludeData<B: Blob = Vec<u8>> {
         pub dest: String,
-        #[serde(with = "serde_bytes")]
-        pub data: Vec<u8>,
+        #[serde(with = "blob")]
+        pub data: B,
+        /// Stored in the entry header, legacy packages never had any.
+        #[serde(skip)]
+        pub attrs: FileAttrs,
     }
 
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-    pub struct Mod {
-        #[serde(with = "serde_bytes")]
-        pub artifact_data: Vec<u8>,
-        pub includes_data: Vec<IncludeData>,
+    #[serde(bound = "")]
+    pub struct Mod<B: Blob = Vec<u8>> {
+        #[serde(with = "blob")]
+        pub artifact_data: B,
+        pub includes_data: Vec<IncludeData<B>>,
     }
 
+    /// Embeds its modules, their entries are stored under `<_id>/` in the container.
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-    pub struct Parent {}
+    #[serde(bound = "")]
+    pub struct Parent<B: Blob = Vec<u8>> {
+        // only legacy packages are deserialized and those never had modules
+        #[serde(skip_deserializing)]
+        pub modules: Vec<ForgeMod<ManifestV1, manifest::Module, Module<B>>>,
+    }
+
+    impl<B: Blob> Parent<B> {
+        /// The embedded module with this `_id`.
+        pub fn module(&self, id: &str) -> Option<&ForgeMod<ManifestV1, manifest::Module, Module<B>>> {
+            self.modules.iter().find(|m| m.manifest._id == id)
+        }
+    }
 
+    /// Everything about an embedded module that is not stored as an entry.
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-    pub struct Module {
+    pub struct ModuleMeta {
+        /// The manifest as the module stores it, see [`ForgeMod::raw_manifest`].
+        #[serde(with = "serde_bytes")]
+        manifest: Vec<u8>,
+        meta: <Module as ForgeModData>::Meta,
+        compression: Compression,
+        signature: Option<PackageSignature>,
+        entries: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(bound = "")]
+    pub struct Module<B: Blob = Vec<u8>> {
         pub _id: String,
         pub required: bool,
         pub suggested: bool,
-        #[serde(with = "serde_bytes")]
-        pub artifact_data: Vec<u8>,
-        pub includes_data: Vec<IncludeData>,
+        #[serde(with = "blob")]
+        pub artifact_data: B,
+        pub includes_data: Vec<IncludeData<B>>,
     }
 
     #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-    pub struct Lib {
-        #[serde(with = "serde_bytes")]
-        pub artifact_data: Vec<u8>,
-        pub includes_data: Vec<IncludeData>,
+    #[serde(bound = "")]
+    pub struct Lib<B: Blob = Vec<u8>> {
+        #[serde(with = "blob")]
+        pub artifact_data: B,
+        pub includes_data: Vec<IncludeData<B>>,
+    }
+
+    /// Sorts includes by `dest`, so packing them gives the same bytes whatever order they were added in.
+    pub fn sort_includes<B: Blob>(includes: &mut [IncludeData<B>]) {
+        includes.sort_by(|a, b| a.dest.cmp(&b.dest).then_with(|| a.data.as_ref().cmp(b.data.as_ref())));
+    }
+
+    /// Artifact first, then every include under its `dest`.
+    fn artifact_entries<'a, B: Blob>(artifact_data: &'a B, includes_data: &'a [IncludeData<B>]) -> Vec<EntryRef<'a>> {
+        let artifact = EntryRef {
+            kind: EntryKind::Artifact,
+            path: "".into(),
+            data: artifact_data.as_ref(),
+            attrs: FileAttrs::default(),
+        };
+
+        std::iter::once(artifact)
+            .chain(includes_data.iter().map(|i| EntryRef {
+                kind: EntryKind::Include,
+                path: i.dest.as_str().into(),
+                data: i.data.as_ref(),
+                attrs: i.attrs,
+            }))
+            .collect()
+    }
+
+    fn from_artifact_entries<B: Blob>(entries: Vec<Entry>) -> Result<(B, Vec<IncludeData<B>>), bincode::Error> {
+        let mut entries = entries.into_iter();
+        let artifact_data = match entries.next() {
+            Some(Entry { kind: EntryKind::Artifact, data, .. }) => B::from(data),
+            _ => return Err(Box::new(bincode::ErrorKind::Custom("package has no artifact".into()))),
+        };
+
+        let includes_data = entries
+            .map(|e| match e.kind {
+                EntryKind::Include => Ok(IncludeData {
+                    dest: e.path,
+                    data: B::from(e.data),
+                    attrs: e.attrs,
+                }),
+                _ => Err(Box::new(bincode::ErrorKind::Custom(format!("unexpected entry {}", e.path)))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok((artifact_data, includes_data))
+    }
+
+    impl<B: Blob> ForgeModData for Mod<B> {
+        type Meta = ();
+
+        fn meta(&self) -> Self::Meta {}
+
+        fn entries(&self) -> Vec<EntryRef<'_>> {
+            artifact_entries(&self.artifact_data, &self.includes_data)
+        }
+
+        fn from_entries(_: Self::Meta, entries: Vec<Entry>) -> Result<Self, bincode::Error> {
+            let (artifact_data, includes_data) = from_artifact_entries(entries)?;
+            Ok(Self { artifact_data, includes_data })
+        }
+    }
+
+    impl<B: Blob> ForgeModData for Parent<B> {
+        type Meta = Vec<ModuleMeta>;
+
+        fn meta(&self) -> Self::Meta {
+            self.modules
+                .iter()
+                .map(|m| ModuleMeta {
+                    manifest: m.manifest_bytes().expect("manifests always serialize").to_vec(),
+                    meta: m.data.meta(),
+                    compression: m.compression,
+                    signature: m.signature.clone(),
+                    entries: m.data.entries().len() as u32,
+                })
+                .collect()
+        }
+
+        fn entries(&self) -> Vec<EntryRef<'_>> {
+            self.modules
+                .iter()
+                .flat_map(|m| {
+                    let id = &m.manifest._id;
+                    m.data.entries().into_iter().map(move |e| EntryRef {
+                        path: match e.kind {
+                            EntryKind::Artifact => id.as_str().into(),
+                            EntryKind::Include => format!("{}/{}", id, e.path).into(),
+                        },
+                        ..e
+                    })
+                })
+                .collect()
+        }
+
+        fn from_entries(meta: Self::Meta, entries: Vec<Entry>) -> Result<Self, bincode::Error> {
+            let mut entries = entries.into_iter();
+
+            let modules = meta
+                .into_iter()
+                .map(|m| {
+                    let manifest: ForgeManifestSafe<manifest::Module, ManifestV1> = decode_manifest(&m.manifest)?;
+                    let id = &manifest._id;
+                    let module_entries = entries
+                        .by_ref()
+                        .take(m.entries as usize)
+                        .map(|e| {
+                            let path = match e.kind {
+                                EntryKind::Artifact if e.path == *id => Some(String::new()),
+                                EntryKind::Include => e.path.strip_prefix(&format!("{}/", id)).map(String::from),
+                                _ => None,
+                            };
+                            match path {
+                                Some(path) => Ok(Entry { path, ..e }),
+                                None => Err(Box::new(bincode::ErrorKind::Custom(format!("unexpected entry {}", e.path)))),
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if module_entries.len() != m.entries as usize {
+                        return Err(Box::new(bincode::ErrorKind::Custom(format!("module {} is truncated", id))));
+                    }
+
+                    let data = Module::from_entries(m.meta, module_entries)?;
+                    let raw_manifest = Bytes::from(m.manifest);
+                    Ok(ForgeMod {
+                        format_version: 1,
+                        kind: "module".into(),
+                        checksums: Checksums::compute(&raw_manifest, &data),
+                        manifest,
+                        data,
+                        compression: m.compression,
+                        signature: m.signature,
+                        raw_manifest,
+                        _marker: PhantomData,
+                    })
+                })
+                .collect::<Result<_, bincode::Error>>()?;
+
+            if let Some(e) = entries.next() {
+                return Err(Box::new(bincode::ErrorKind::Custom(format!("unexpected entry {}", e.path))));
+            }
+
+            Ok(Self { modules })
+        }
+    }
+
+    impl<B: Blob> ForgeModData for Module<B> {
+        /// `_id`, `required` and `suggested`
+        type Meta = (String, bool, bool);
+
+        fn meta(&self) -> Self::Meta {
+            (self._id.clone(), self.required, self.suggested)
+        }
+
+        fn entries(&self) -> Vec<EntryRef<'_>> {
+            artifact_entries(&self.artifact_data, &self.includes_data)
+        }
+
+        fn from_entries((_id, required, suggested): Self::Meta, entries: Vec<Entry>) -> Result<Self, bincode::Error> {
+            let (artifact_data, includes_data) = from_artifact_entries(entries)?;
+            Ok(Self {
+                _id,
+                required,
+                suggested,
+                artifact_data,
+                includes_data,
+            })
+        }
     }
 
-    impl ForgeModData for Mod {}
-    impl ForgeModData for Parent {}
-    impl ForgeModData for Module {}
-    impl ForgeModData for Lib {}
+    impl<B: Blob> ForgeModData for Lib<B> {
+        type Meta = ();
+
+        fn meta(&self) -> Self::Meta {}
+
+        fn entries(&self) -> Vec<EntryRef<'_>> {
+            artifact_entries(&self.artifact_data, &self.includes_data)
+        }
+
+        fn from_entries(_: Self::Meta, entries: Vec<Entry>) -> Result<Self, bincode::Error> {
+            let (artifact_data, includes_data) = from_artifact_entries(entries)?;
+            Ok(Self { artifact_data, includes_data })
+        }
+    }
 }
 
 /// It is intended that the manifest is build first, then the data is added.
@@ -448,6 +665,15 @@ pub mod data {
 pub struct ModBuilder<Type: ManifestComponent, Data: ForgeModData> {
     pub(self) _manifest: ForgeManifest<Type, ManifestV1>,
     pub(self) _inner: Data,
+    pub(self) _compression: Compression,
+}
+
+impl<Type: ManifestComponent, Data: ForgeModData> ModBuilder<Type, Data> {
+    /// Codec and level the built mod is packed with, xz at level 9 unless set.
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self._compression = compression;
+        self
+    }
 }
 
 impl ModBuilder<manifest::Mod, data::Mod> {
@@ -458,6 +684,7 @@ impl ModBuilder<manifest::Mod, data::Mod> {
                 artifact_data,
                 includes_data: vec![],
             },
+            _compression: Compression::default(),
         }
     }
 
@@ -473,20 +700,30 @@ impl ModBuilder<manifest::Mod, data::Mod> {
                 artifact_data,
                 includes_data: vec![],
             },
+            _compression: Compression::default(),
         })
     }
 
-    pub fn includes(&mut self, includes: Vec<data::IncludeData>) -> &mut Self {
+    /// Includes are kept in a canonical order, see [`data::sort_includes`].
+    pub fn includes(&mut self, mut includes: Vec<data::IncludeData>) -> &mut Self {
+        data::sort_includes(&mut includes);
         self._inner.includes_data = includes;
         self
     }
 
     pub fn build(self) -> ForgeMod<ManifestV1, manifest::Mod, data::Mod> {
+        let manifest = self._manifest.into();
+        let raw_manifest = Bytes::from(encode_manifest(&manifest).expect("manifests always serialize"));
+
         ForgeMod {
             format_version: 1,
             kind: "mod".into(),
-            manifest: self._manifest.into(),
+            checksums: Checksums::compute(&raw_manifest, &self._inner),
+            manifest,
             data: self._inner,
+            compression: self._compression,
+            signature: None,
+            raw_manifest,
             _marker: PhantomData,
         }
     }
@@ -496,16 +733,35 @@ impl ModBuilder<manifest::Parent, data::Parent> {
     pub fn new_module_parent(manifest: ForgeManifest<manifest::Parent, ManifestV1>) -> Self {
         Self {
             _manifest: manifest,
-            _inner: data::Parent {},
+            _inner: data::Parent { modules: vec![] },
+            _compression: Compression::default(),
         }
     }
 
+    /// Embeds `modules`, which have to be the ones listed in the manifest. They are kept sorted by `_id`.
+    pub fn modules(
+        &mut self,
+        mut modules: Vec<ForgeMod<ManifestV1, manifest::Module, data::Module>>,
+    ) -> Result<&mut Self, ModuleError> {
+        check_modules(&self._manifest.inner.modules, &modules)?;
+        modules.sort_by(|a, b| a.manifest._id.cmp(&b.manifest._id));
+        self._inner.modules = modules;
+        Ok(self)
+    }
+
     pub fn build(self) -> ForgeMod<ManifestV1, manifest::Parent, data::Parent> {
+        let manifest = self._manifest.into();
+        let raw_manifest = Bytes::from(encode_manifest(&manifest).expect("manifests always serialize"));
+
         ForgeMod {
             format_version: 1,
             kind: "parent".into(),
-            manifest: self._manifest.into(),
+            checksums: Checksums::compute(&raw_manifest, &self._inner),
+            manifest,
             data: self._inner,
+            compression: self._compression,
+            signature: None,
+            raw_manifest,
             _marker: PhantomData,
         }
     }
@@ -525,6 +781,7 @@ impl ModBuilder<manifest::Module, data::Module> {
                 includes_data: vec![],
             },
             _manifest: manifest,
+            _compression: Compression::default(),
         }
     }
 
@@ -541,20 +798,30 @@ impl ModBuilder<manifest::Module, data::Module> {
                 includes_data: vec![],
             },
             _manifest: manifest,
+            _compression: Compression::default(),
         })
     }
 
-    pub fn includes(&mut self, includes: Vec<data::IncludeData>) -> &mut Self {
+    /// Includes are kept in a canonical order, see [`data::sort_includes`].
+    pub fn includes(&mut self, mut includes: Vec<data::IncludeData>) -> &mut Self {
+        data::sort_includes(&mut includes);
         self._inner.includes_data = includes;
         self
     }
 
     pub fn build(self) -> ForgeMod<ManifestV1, manifest::Module, data::Module> {
+        let manifest = self._manifest.into();
+        let raw_manifest = Bytes::from(encode_manifest(&manifest).expect("manifests always serialize"));
+
         ForgeMod {
             format_version: 1,
             kind: "module".into(),
-            manifest: self._manifest.into(),
+            checksums: Checksums::compute(&raw_manifest, &self._inner),
+            manifest,
             data: self._inner,
+            compression: self._compression,
+            signature: None,
+            raw_manifest,
             _marker: PhantomData,
         }
     }
@@ -568,6 +835,7 @@ impl ModBuilder<manifest::Lib, data::Lib> {
                 includes_data: vec![],
             },
             _manifest: manifest,
+            _compression: Compression::default(),
         }
     }
 
@@ -581,54 +849,383 @@ impl ModBuilder<manifest::Lib, data::Lib> {
                 includes_data: vec![],
             },
             _manifest: manifest,
+            _compression: Compression::default(),
         })
     }
 
-    pub fn includes(&mut self, includes: Vec<data::IncludeData>) -> &mut Self {
+    /// Includes are kept in a canonical order, see [`data::sort_includes`].
+    pub fn includes(&mut self, mut includes: Vec<data::IncludeData>) -> &mut Self {
+        data::sort_includes(&mut includes);
         self._inner.includes_data = includes;
         self
     }
 
     pub fn build(self) -> ForgeMod<ManifestV1, manifest::Lib, data::Lib> {
+        let manifest = self._manifest.into();
+        let raw_manifest = Bytes::from(encode_manifest(&manifest).expect("manifests always serialize"));
+
         ForgeMod {
             format_version: 1,
             kind: "lib".into(),
-            manifest: self._manifest.into(),
+            checksums: Checksums::compute(&raw_manifest, &self._inner),
+            manifest,
             data: self._inner,
+            compression: self._compression,
+            signature: None,
+            raw_manifest,
             _marker: PhantomData,
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 pub struct IncludeDataBuilder {
     pub(self) _inners: Vec<data::IncludeData>,
+    pub(self) _report: IncludeReport,
+    pub(self) _keep_mtimes: bool,
 }
 
 impl IncludeDataBuilder {
     pub fn new() -> Self {
-        Self { _inners: vec![] }
+        Self {
+            _inners: vec![],
+            _report: IncludeReport::default(),
+            _keep_mtimes: false,
+        }
+    }
+
+    /// Records the modification time of the files added from now on.
+    /// Off by default, so packing the same files again gives the same bytes.
+    pub fn keep_mtimes(&mut self, keep: bool) -> &mut Self {
+        self._keep_mtimes = keep;
+        self
     }
 
     pub fn add_raw(&mut self, dest: String, data: Vec<u8>) -> &mut Self {
-        self._inners.push(data::IncludeData { dest, data });
+        self.add_raw_with_attrs(dest, data, FileAttrs::default())
+    }
+
+    pub fn add_raw_with_attrs(&mut self, dest: String, data: Vec<u8>, attrs: FileAttrs) -> &mut Self {
+        self._inners.push(data::IncludeData { dest, data, attrs });
 
         self
     }
 
+    /// Of the permissions only the executable bit is kept, as mode `0o755`, the rest
+    /// depends on the umask of whoever built the mod.
     pub fn add(&mut self, dest: String, src: PathBuf) -> Result<&mut Self, std::io::Error> {
-        let data = std::fs::read(src)?;
+        let data = std::fs::read(&src)?;
+        let metadata = std::fs::metadata(&src)?;
+
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            (metadata.permissions().mode() & 0o111 != 0).then_some(0o755)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+
+        let mtime = match self._keep_mtimes {
+            true => Some(
+                metadata
+                    .modified()?
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs()),
+            ),
+            false => None,
+        };
+
+        Ok(self.add_raw_with_attrs(dest, data, FileAttrs { mode, mtime }))
+    }
+
+    /// Adds every file under `src_dir`, at its path relative to `src_dir` under `dest_prefix`.
+    /// Files are added in path order and only if they pass `filters`.
+    ///
+    /// [`DEFAULT_IGNORES`] and the [`FORGEIGNORE`] files found along the way are applied
+    /// like gitignore files, everything left out ends up in [`Self::report`].
+    pub fn add_dir(
+        &mut self,
+        dest_prefix: String,
+        src_dir: PathBuf,
+        filters: &IncludeFilters,
+    ) -> Result<&mut Self, std::io::Error> {
+        let (include, exclude) = filters.compile()?;
+        let mut ignores = vec![(0, IgnoreFile::defaults()?)];
+
+        let mut walk = walkdir::WalkDir::new(&src_dir).sort_by_file_name().into_iter();
+        while let Some(entry) = walk.next() {
+            let entry = entry?;
+            let is_dir = entry.file_type().is_dir();
+            ignores.retain(|(depth, _)| *depth == 0 || *depth < entry.depth());
+
+            // safety: every entry is below src_dir
+            let relative = dest_path(entry.path().strip_prefix(&src_dir).unwrap())?;
+            if entry.depth() > 0 {
+                if let Some(reason) = IgnoreFile::check(&ignores, &relative, is_dir) {
+                    self._report.skipped.push(SkippedFile { path: relative, reason });
+                    if is_dir {
+                        walk.skip_current_dir();
+                    }
+                    continue;
+                }
+            }
 
-        self._inners.push(data::IncludeData { dest, data });
+            if is_dir {
+                let ignore_file = entry.path().join(FORGEIGNORE);
+                if ignore_file.is_file() {
+                    ignores.push((entry.depth(), IgnoreFile::read(&relative, &ignore_file)?));
+                }
+                continue;
+            }
+            if !entry.file_type().is_file() {
+                self._report.skipped.push(SkippedFile {
+                    path: relative,
+                    reason: SkipReason::NotAFile,
+                });
+                continue;
+            }
+
+            if !(filters.include.is_empty() || include.is_match(&relative)) || exclude.is_match(&relative) {
+                self._report.skipped.push(SkippedFile {
+                    path: relative,
+                    reason: SkipReason::Filtered,
+                });
+                continue;
+            }
+
+            let dest = match dest_prefix.trim_end_matches('/') {
+                "" => relative,
+                prefix => format!("{}/{}", prefix, relative),
+            };
+            self.add(dest, entry.into_path())?;
+        }
 
         Ok(self)
     }
 
+    /// Adds what a manifest include points at, every file below it if it is a directory.
+    pub fn add_include(&mut self, include: &manifest::Include) -> Result<&mut Self, std::io::Error> {
+        let dest = dest_path(&include.bs_dest)?;
+
+        match include.local_src.is_dir() {
+            true => self.add_dir(dest, include.local_src.clone(), &IncludeFilters::default()),
+            false => self.add(dest, include.local_src.clone()),
+        }
+    }
+
+    /// What was left out of the directories added so far, and why.
+    pub fn report(&self) -> &IncludeReport {
+        &self._report
+    }
+
     pub fn build(self) -> Vec<data::IncludeData> {
         self._inners
     }
 }
 
+/// Files left out while collecting includes, see [`IncludeDataBuilder::report`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct IncludeReport {
+    pub skipped: Vec<SkippedFile>,
+}
+
+impl Display for IncludeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for skipped in &self.skipped {
+            writeln!(f, "skipped {}", skipped)?;
+        }
+        Ok(())
+    }
+}
+
+/// A file or directory left out of the includes, `path` is relative to the added directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+impl Display for SkippedFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SkipReason {
+    /// Matched a pattern of an ignore file, `file` is `None` for [`DEFAULT_IGNORES`].
+    Ignored { file: Option<String>, pattern: String },
+    /// Not picked up by the [`IncludeFilters`].
+    Filtered,
+    /// A symlink, FIFO or anything else that is not a regular file or folder, links are not followed.
+    NotAFile,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ignored { file: Some(file), pattern } => write!(f, "ignored by `{}` in {}", pattern, file),
+            Self::Ignored { file: None, pattern } => write!(f, "ignored by default rule `{}`", pattern),
+            Self::Filtered => write!(f, "not matched by the include filters"),
+            Self::NotAFile => write!(f, "not a regular file"),
+        }
+    }
+}
+
+/// Name of the gitignore style files read by [`IncludeDataBuilder::add_dir`].
+pub const FORGEIGNORE: &str = ".forgeignore";
+
+/// Build leftovers and editor files never worth shipping, a [`FORGEIGNORE`] can
+/// bring them back with `!`.
+pub const DEFAULT_IGNORES: &[&str] = &[
+    FORGEIGNORE,
+    "*.pdb",
+    "obj/",
+    "*~",
+    "*.swp",
+    "*.tmp",
+    ".#*",
+    ".DS_Store",
+    "Thumbs.db",
+];
+
+/// Patterns of one ignore file, applying below `base`.
+struct IgnoreFile {
+    base: String,
+    file: Option<String>,
+    rules: Vec<IgnoreRule>,
+}
+
+struct IgnoreRule {
+    pattern: String,
+    matcher: globset::GlobMatcher,
+    negate: bool,
+    dir_only: bool,
+}
+
+impl IgnoreFile {
+    fn defaults() -> Result<Self, std::io::Error> {
+        Ok(Self {
+            base: String::new(),
+            file: None,
+            rules: DEFAULT_IGNORES.iter().filter_map(|line| IgnoreRule::parse(line)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn read(base: &str, path: &std::path::Path) -> Result<Self, std::io::Error> {
+        let file = match base {
+            "" => FORGEIGNORE.to_string(),
+            base => format!("{}/{}", base, FORGEIGNORE),
+        };
+        let rules = std::fs::read_to_string(path)?
+            .lines()
+            .filter_map(IgnoreRule::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", file, e)))?;
+
+        Ok(Self {
+            base: base.to_string(),
+            file: Some(file),
+            rules,
+        })
+    }
+
+    /// Deeper files win over the ones above them, later patterns over earlier ones.
+    fn check(files: &[(usize, Self)], relative: &str, is_dir: bool) -> Option<SkipReason> {
+        for (_, file) in files.iter().rev() {
+            let path = match file.base.as_str() {
+                "" => relative,
+                base => &relative[base.len() + 1..],
+            };
+            let rule = file.rules.iter().rev().find(|r| (is_dir || !r.dir_only) && r.matcher.is_match(path));
+            if let Some(rule) = rule {
+                return (!rule.negate).then(|| SkipReason::Ignored {
+                    file: file.file.clone(),
+                    pattern: rule.pattern.clone(),
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl IgnoreRule {
+    fn parse(line: &str) -> Option<Result<Self, std::io::Error>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negate, glob) = match line.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, glob) = match glob.strip_suffix('/') {
+            Some(glob) => (true, glob),
+            None => (false, glob),
+        };
+        // like gitignore, a pattern without an inner `/` matches at any depth
+        let glob = match glob.strip_prefix('/') {
+            Some(glob) => glob.to_string(),
+            None if glob.contains('/') => glob.to_string(),
+            None => format!("**/{}", glob),
+        };
+
+        let matcher = globset::GlobBuilder::new(&glob)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+        Some(matcher.map(|glob| Self {
+            pattern: line.to_string(),
+            matcher: glob.compile_matcher(),
+            negate,
+            dir_only,
+        }))
+    }
+}
+
+/// Globs deciding which files [`IncludeDataBuilder::add_dir`] picks up, matched against
+/// `/` separated paths relative to the directory.
+///
+/// Without include globs every file is included, excludes always win.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct IncludeFilters {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl IncludeFilters {
+    fn compile(&self) -> Result<(globset::GlobSet, globset::GlobSet), std::io::Error> {
+        let set = |globs: &[String]| {
+            globs
+                .iter()
+                .try_fold(globset::GlobSetBuilder::new(), |mut set, glob| {
+                    set.add(globset::Glob::new(glob)?);
+                    Ok(set)
+                })
+                .and_then(|set| set.build())
+                .map_err(|e: globset::Error| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+        };
+
+        Ok((set(&self.include)?, set(&self.exclude)?))
+    }
+}
+
+/// `/` separated form of a path, as includes are stored.
+fn dest_path(path: &std::path::Path) -> Result<String, std::io::Error> {
+    let parts = path
+        .components()
+        .map(|c| {
+            c.as_os_str().to_str().ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} is not valid UTF-8", path.display()))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(parts.join("/"))
+}
+
 #[derive(Debug)]
 pub enum ForgeManifestTypes {
     Mod(ForgeManifest<manifest::Mod, ManifestV1>),
@@ -638,11 +1235,98 @@ pub enum ForgeManifestTypes {
 }
 
 #[derive(Debug)]
-pub enum ForgeModTypes {
-    Mod(ForgeMod<ManifestV1, manifest::Mod, data::Mod>),
-    Parent(ForgeMod<ManifestV1, manifest::Parent, data::Parent>),
-    Module(ForgeMod<ManifestV1, manifest::Module, data::Module>),
-    Lib(ForgeMod<ManifestV1, manifest::Lib, data::Lib>),
+pub enum ForgeModTypes<B: Blob = Vec<u8>> {
+    Mod(ForgeMod<ManifestV1, manifest::Mod, data::Mod<B>>),
+    Parent(ForgeMod<ManifestV1, manifest::Parent, data::Parent<B>>),
+    Module(ForgeMod<ManifestV1, manifest::Module, data::Module<B>>),
+    Lib(ForgeMod<ManifestV1, manifest::Lib, data::Lib<B>>),
+}
+
+/// Why the modules embedded in a parent do not match its manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleError {
+    /// Listed in the manifest but not embedded.
+    Missing { id: String },
+    /// Embedded but not listed in the manifest.
+    Unlisted { id: String },
+    /// Embedded more than once.
+    Duplicate { id: String },
+    /// Listed path that does not name a module.
+    InvalidPath { path: PathBuf },
+}
+
+impl Display for ModuleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing { id } => write!(f, "module {} is not embedded", id),
+            Self::Unlisted { id } => write!(f, "module {} is not listed in the manifest", id),
+            Self::Duplicate { id } => write!(f, "module {} is embedded more than once", id),
+            Self::InvalidPath { path } => write!(f, "{} does not name a module", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ModuleError {}
+
+/// Modules are listed in the manifest by the path of their folder or manifest,
+/// named after the module `_id`. Only a `.json` extension is taken off, ids can have dots.
+fn check_modules<B: Blob>(
+    listed: &[PathBuf],
+    modules: &[ForgeMod<ManifestV1, manifest::Module, data::Module<B>>],
+) -> Result<(), ModuleError> {
+    let mut embedded = HashSet::new();
+    for m in modules {
+        if !embedded.insert(m.manifest._id.as_str()) {
+            return Err(ModuleError::Duplicate { id: m.manifest._id.clone() });
+        }
+    }
+
+    let listed = listed
+        .iter()
+        .map(|path| {
+            let name = path.file_name().and_then(|n| n.to_str());
+            match name.map(|n| n.strip_suffix(".json").unwrap_or(n)) {
+                Some(id) if !id.is_empty() => Ok(id),
+                _ => Err(ModuleError::InvalidPath { path: path.clone() }),
+            }
+        })
+        .collect::<Result<HashSet<_>, _>>()?;
+    if let Some(id) = listed.iter().find(|id| !embedded.contains(*id)) {
+        return Err(ModuleError::Missing { id: id.to_string() });
+    }
+    match modules.iter().find(|m| !listed.contains(m.manifest._id.as_str())) {
+        Some(m) => Err(ModuleError::Unlisted { id: m.manifest._id.clone() }),
+        None => Ok(()),
+    }
+}
+
+impl<B: Blob> ForgeMod<ManifestV1, manifest::Parent, data::Parent<B>> {
+    /// Checks that the embedded modules are the ones listed in the manifest, done on unpack.
+    pub fn check_modules(&self) -> Result<(), ModuleError> {
+        check_modules(&self.manifest.inner.modules, &self.data.modules)
+    }
+}
+
+impl ForgeManifestTypes {
+    /// Checks the include count and every path of the manifest against `limits`.
+    fn check_limits(&self, limits: &UnpackLimits) -> Result<(), LimitError> {
+        let (includes, mut paths): (&[manifest::Include], Vec<&PathBuf>) = match self {
+            Self::Mod(m) => (&m.inner.includes, [&m.inner.pre_exec, &m.inner.post_exec].into_iter().flatten().collect()),
+            Self::Parent(m) => (&[], [&m.inner.pre_exec, &m.inner.post_exec].into_iter().flatten().collect()),
+            Self::Module(m) => (&m.inner.includes, [&m.inner.pre_exec, &m.inner.post_exec].into_iter().flatten().collect()),
+            Self::Lib(m) => (&m.inner.includes, [&m.inner.pre_exec, &m.inner.post_exec].into_iter().flatten().collect()),
+        };
+        if let Self::Parent(m) = self {
+            paths.extend(&m.inner.modules);
+        }
+
+        limits.check_include_count(includes.len())?;
+        for path in includes.iter().flat_map(|i| [&i.bs_dest, &i.local_src]).chain(paths) {
+            limits.check_path(path)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Display for ForgeManifestTypes {
@@ -656,7 +1340,7 @@ impl Display for ForgeManifestTypes {
     }
 }
 
-impl Display for ForgeModTypes {
+impl<B: Blob> Display for ForgeModTypes<B> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ForgeModTypes::Mod(_) => write!(f, "mod"),
@@ -667,46 +1351,137 @@ impl Display for ForgeModTypes {
     }
 }
 
-/// THIS IS THE FUNCTION THAT YOU WANT TO USE
-/// DON'T USE ANYTHING ELSE!!!!
-/// I promise i will make it better!
+/// Unpacks a package of format version 1 only,
+/// [`unpack_forgemod`](super::unpack::unpack_forgemod) takes every version and migrates it.
 pub fn unpack_v1_forgemod<'a, T: Into<&'a [u8]>>(data: T) -> Result<ForgeModTypes, Box<dyn std::error::Error>> {
-    let data = data.into();
-    let generic = ForgeModGeneric::from_bytes(data)?;
-    let kind = generic.kind.as_str();
-    let format_version = generic.format_version;
+    unpack_v1_forgemod_from(data.into())
+}
+
+/// Streaming version of [`unpack_v1_forgemod`], the package is decompressed while it is read.
+pub fn unpack_v1_forgemod_from<R: Read>(reader: R) -> Result<ForgeModTypes, Box<dyn std::error::Error>> {
+    unpack_v1_forgemod_with_limits(reader, &UnpackLimits::default())
+}
+
+/// [`unpack_v1_forgemod_from`] with other limits than the default ones.
+/// Going over any of them fails with a [`LimitError`] before the memory is allocated.
+pub fn unpack_v1_forgemod_with_limits<R: Read>(
+    reader: R,
+    limits: &UnpackLimits,
+) -> Result<ForgeModTypes, Box<dyn std::error::Error>> {
+    ContainerReader::open(reader, limits)
+        .map_err(surface_error)
+        .and_then(unpack_v1_container)
+        .map_err(local_error)
+}
+
+/// Unpacks a forge mod held in memory, a memory mapped package for instance.
+///
+/// The artifact and includes of packages packed with [`Codec::None`](super::forgemod::Codec::None)
+/// point into `data` instead of being copied out of it.
+pub fn unpack_v1_forgemod_shared(data: Bytes) -> Result<ForgeModTypes<Bytes>, Box<dyn std::error::Error>> {
+    ContainerReader::open_shared(data, &UnpackLimits::default())
+        .map_err(surface_error)
+        .and_then(unpack_v1_container)
+        .map_err(local_error)
+}
+
+pub(crate) fn unpack_v1_container<B: Blob>(
+    container: ContainerReader,
+) -> Result<ForgeModTypes<B>, Box<dyn std::error::Error + Send + Sync>> {
+    let format_version = container.generic.format_version;
 
     if format_version != 1 {
         return Err("cannot find v1 manifest information.".into());
     }
 
-    match kind {
+    match container.generic.kind.as_str() {
         "mod" => {
-            ForgeMod::<ManifestV1, manifest::Mod, data::Mod>::from_bytes(data)
-                .map(|v| ForgeModTypes::Mod(v))
-                .map_err(|e| e.into())
+            ForgeMod::<ManifestV1, manifest::Mod, data::Mod<B>>::read_remaining(container)
+                .map(ForgeModTypes::Mod)
+                .map_err(surface_error)
         },
         "parent" => {
-            ForgeMod::<ManifestV1, manifest::Parent, data::Parent>::from_bytes(data)
-                .map(|v| ForgeModTypes::Parent(v))
-                .map_err(|e| e.into())
+            // legacy packages predate embedded modules, there is nothing to check them against
+            let legacy = container.info.version == 0;
+            let parent = ForgeMod::<ManifestV1, manifest::Parent, data::Parent<B>>::read_remaining(container)
+                .map_err(surface_error)?;
+            if !legacy {
+                parent.check_modules()?;
+            }
+            Ok(ForgeModTypes::Parent(parent))
         },
         "module" => {
-            ForgeMod::<ManifestV1, manifest::Module, data::Module>::from_bytes(data)
-                .map(|v| ForgeModTypes::Module(v))
-                .map_err(|e| e.into())
+            ForgeMod::<ManifestV1, manifest::Module, data::Module<B>>::read_remaining(container)
+                .map(ForgeModTypes::Module)
+                .map_err(surface_error)
         },
         "lib" => {
-            ForgeMod::<ManifestV1, manifest::Lib, data::Lib>::from_bytes(data)
-                .map(|v| ForgeModTypes::Lib(v))
-                .map_err(|e| e.into())
+            ForgeMod::<ManifestV1, manifest::Lib, data::Lib<B>>::read_remaining(container)
+                .map(ForgeModTypes::Lib)
+                .map_err(surface_error)
         },
         _ => Err("unknown kind".into()),
     }
 }
 
+/// Reads only the manifest of a packed forge mod, the artifact and includes are never decompressed.
+pub fn peek_manifest<'a, T: Into<&'a [u8]>>(data: T) -> Result<ForgeManifestTypes, Box<dyn std::error::Error>> {
+    peek_manifest_from(data.into())
+}
+
+/// Streaming version of [`peek_manifest`], nothing past the manifest is read from `reader`.
+pub fn peek_manifest_from<R: Read>(reader: R) -> Result<ForgeManifestTypes, Box<dyn std::error::Error>> {
+    ContainerReader::open(reader, &UnpackLimits::default())
+        .map_err(surface_error)
+        .and_then(peek_v1_container)
+        .map_err(local_error)
+}
+
+pub(crate) fn peek_v1_container(
+    mut container: ContainerReader,
+) -> Result<ForgeManifestTypes, Box<dyn std::error::Error + Send + Sync>> {
+    if container.generic.format_version != 1 {
+        return Err("cannot find v1 manifest information.".into());
+    }
+
+    match container.generic.kind.as_str() {
+        "mod" => Ok(ForgeManifestTypes::Mod(
+            container.manifest::<ForgeManifestSafe<manifest::Mod, ManifestV1>>().map_err(surface_error)?.into(),
+        )),
+        "parent" => Ok(ForgeManifestTypes::Parent(
+            container.manifest::<ForgeManifestSafe<manifest::Parent, ManifestV1>>().map_err(surface_error)?.into(),
+        )),
+        "module" => Ok(ForgeManifestTypes::Module(
+            container.manifest::<ForgeManifestSafe<manifest::Module, ManifestV1>>().map_err(surface_error)?.into(),
+        )),
+        "lib" => Ok(ForgeManifestTypes::Lib(
+            container.manifest::<ForgeManifestSafe<manifest::Lib, ManifestV1>>().map_err(surface_error)?.into(),
+        )),
+        _ => Err("unknown kind".into()),
+    }
+}
+
 pub fn parse_v1_forgemanifest<'a, T: Into<&'a [u8]>>(data: T) -> Result<ForgeManifestTypes, Box<dyn std::error::Error>> {
+    parse_v1_forgemanifest_with_limits(data, &UnpackLimits::default())
+}
+
+/// [`parse_v1_forgemanifest`] with other limits than the default ones.
+pub fn parse_v1_forgemanifest_with_limits<'a, T: Into<&'a [u8]>>(
+    data: T,
+    limits: &UnpackLimits,
+) -> Result<ForgeManifestTypes, Box<dyn std::error::Error>> {
     let data = data.into();
+    if data.len() as u64 > limits.max_total_size {
+        return Err(LimitError::TotalSize { limit: limits.max_total_size }.into());
+    }
+
+    let manifest = parse_v1_forgemanifest_unchecked(data)?;
+    manifest.check_limits(limits)?;
+
+    Ok(manifest)
+}
+
+fn parse_v1_forgemanifest_unchecked(data: &[u8]) -> Result<ForgeManifestTypes, Box<dyn std::error::Error>> {
     let generic = ForgeManifestGeneric::from_bytes(data)?;
     let kind = generic._type.as_str();
     let manifest_version = generic.manifest_version;
@@ -1,10 +1,31 @@
 #[cfg(test)]
 mod tests {
+    use std::{
+        io::{Cursor, Read},
+        path::PathBuf,
+    };
+
     use semver::{Version, VersionReq};
+    use serde::{Deserialize, Serialize};
 
     use crate::structs::{
-        forgemod::{ForgeMod, ForgeModGeneric},
-        v1::{IncludeDataBuilder, ManifestBuilder, ModBuilder, unpack_v1_forgemod},
+        delta::{DeltaError, DeltaPackage, Patch},
+        forgemod::{
+            open_include, read_index, sha256, sniff, ChecksumError, Codec, Compression, ContainerInfo, EntryKind, FileAttrs,
+            ForgeMod, ForgeModGeneric, LimitError, SignatureError, SigningKey, UnpackLimits, CONTAINER_VERSION, MAGIC,
+        },
+        install::{
+            find_conflicts, plan_install, uninstall, CommandHooks, ConflictPolicy, FileOwner, HookStage, InstallError,
+            InstallHooks, InstallState, FAIL_AFTER, STATE_FILE, TRANSACTION_DIR,
+        },
+        manifest::{ForgeManifestSafe, ManifestComponent},
+        unpack::{parse_forgemanifest, read_versioned_forgemod, unpack_forgemod, VersionedForgeMod},
+        v1::{
+            parse_v1_forgemanifest, parse_v1_forgemanifest_with_limits, peek_manifest, unpack_v1_forgemod,
+            unpack_v1_forgemod_from, unpack_v1_forgemod_shared, unpack_v1_forgemod_with_limits, ForgeManifestTypes,
+            data, manifest, ForgeModTypes, IncludeBuilder, IncludeDataBuilder, IncludeFilters, ManifestBuilder, ManifestV1,
+            ModBuilder, ModuleError, SkipReason,
+        },
     };
 
     #[test]
@@ -91,4 +112,1461 @@ mod tests {
         let tmod2 = ForgeMod::from_bytes(&*bin).unwrap();
         assert_eq!(_tmod.build(), tmod2)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_pack_compresses() {
+        let _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xAB; 64 * 1024],
+        );
+
+        let bin = _tmod.clone().build().pack().unwrap();
+        assert!(bin.len() < 64 * 1024);
+        assert_eq!(ForgeMod::from_bytes(&*bin).unwrap(), _tmod.build())
+    }
+
+    #[test]
+    fn test_legacy_layout() {
+        let _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF, 0xFF],
+        )
+        .build();
+
+        // packages published before compression worked: raw bincode with an empty xz stream appended
+        let legacy = bincode::serialize(&_tmod).unwrap();
+        let legacy = xz2::write::XzEncoder::new(legacy, 9).finish().unwrap();
+
+        assert_eq!(ForgeModGeneric::from_bytes(&*legacy).unwrap().kind, "mod");
+        assert_eq!(ForgeMod::from_bytes(&*legacy).unwrap(), _tmod);
+    }
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let mut _tmod = ModBuilder::new_lib_raw(
+            ManifestBuilder::new_lib(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF, 0xFF],
+        );
+        _tmod.includes(IncludeDataBuilder::new().add_raw("./Libs".to_string(), vec![0xFF, 0xFF]).clone().build());
+
+        let mut bin = Vec::new();
+        _tmod.clone().build().pack_to(&mut bin).unwrap();
+        assert_eq!(bin, _tmod.clone().build().pack().unwrap());
+
+        let tmod2 = ForgeMod::read_from(std::io::Cursor::new(&bin)).unwrap();
+        assert_eq!(_tmod.build(), tmod2);
+
+        let mod_ = unpack_v1_forgemod_from(std::io::Cursor::new(&bin)).unwrap().to_string();
+        assert_eq!(mod_, "lib")
+    }
+
+    #[test]
+    fn test_sniff() {
+        let _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF, 0xFF],
+        )
+        .build();
+
+        let bin = _tmod.pack().unwrap();
+        assert!(bin.starts_with(&MAGIC));
+        assert_eq!(sniff(&bin), Some(ContainerInfo {
+                version: CONTAINER_VERSION,
+                compression: Compression::default(),
+            }));
+
+        let legacy = bincode::serialize(&_tmod).unwrap();
+        assert_eq!(sniff(&legacy), Some(ContainerInfo {
+                version: 0,
+                compression: Compression::new(Codec::None, 0),
+            }));
+
+        assert_eq!(sniff(b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xFF\xFF"), None);
+        assert_eq!(sniff(b"FORGEMOD\x07\x00\x01\x09"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn test_peek_manifest() {
+        let mut _manifest = ManifestBuilder::new_mod(
+            "pp".to_string(),
+            Version::new(0, 1, 2),
+            VersionReq::parse("=1.23.4").unwrap(),
+        );
+        _manifest.description("peeked".to_string());
+        // noise, so the compressed data stays large
+        let artifact = (0..64 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        let _tmod = ModBuilder::new_mod_raw(_manifest.build(), artifact).build();
+
+        // cut off most of the compressed data, the manifest must not need it
+        let bin = _tmod.pack().unwrap();
+        let bin = &bin[..bin.len() / 2];
+        assert!(unpack_v1_forgemod(bin).is_err());
+
+        let manifest = match peek_manifest(bin).unwrap() {
+            ForgeManifestTypes::Mod(m) => m,
+            m => panic!("expected a mod manifest, got {}", m),
+        };
+        assert_eq!(manifest.inner.description, "peeked");
+        assert_eq!(ForgeManifestSafe::from(manifest), _tmod.manifest);
+
+        let legacy = bincode::serialize(&_tmod).unwrap();
+        assert_eq!(peek_manifest(&*legacy).unwrap().to_string(), "pp");
+    }
+
+    #[test]
+    fn test_open_include() {
+        let mut _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF; 1024],
+        );
+        _tmod.includes(
+            IncludeDataBuilder::new()
+                .add_raw("Libs/a.dll".to_string(), vec![0xAA; 2048])
+                .add_raw("Libs/b.dll".to_string(), vec![0xBB, 0xBC])
+                .clone()
+                .build(),
+        );
+        let bin = _tmod.build().pack().unwrap();
+
+        let index = read_index(Cursor::new(&bin)).unwrap();
+        let paths: Vec<_> = index.iter().map(|e| (e.kind, e.path.as_str(), e.len)).collect();
+        assert_eq!(
+            paths,
+            vec![
+                (EntryKind::Artifact, "", 1024),
+                (EntryKind::Include, "Libs/a.dll", 2048),
+                (EntryKind::Include, "Libs/b.dll", 2),
+            ]
+        );
+
+        let mut data = Vec::new();
+        open_include(Cursor::new(&bin), "Libs/b.dll").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![0xBB, 0xBC]);
+
+        // the container does not have to start at the beginning of the stream
+        let mut prefixed = Cursor::new([b"garbage".as_slice(), &bin].concat());
+        prefixed.set_position(7);
+        let mut data = Vec::new();
+        open_include(prefixed, "Libs/a.dll").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![0xAA; 2048]);
+
+        assert!(open_include(Cursor::new(&bin), "Libs/c.dll").is_err());
+    }
+
+    #[test]
+    fn test_codecs() {
+        for compression in [
+            Compression::new(Codec::None, 0),
+            Compression::new(Codec::Xz, 1),
+            Compression::new(Codec::Zstd, 19),
+            Compression::fast(),
+        ] {
+            let mut _tmod = ModBuilder::new_mod_raw(
+                ManifestBuilder::new_mod(
+                    "pp".to_string(),
+                    Version::new(0, 1, 2),
+                    VersionReq::parse("=1.23.4").unwrap(),
+                )
+                .build(),
+                vec![0xAB; 64 * 1024],
+            );
+            _tmod.compression(compression);
+            _tmod.includes(IncludeDataBuilder::new().add_raw("Libs/a.dll".to_string(), vec![0xAA; 2048]).clone().build());
+
+            let bin = _tmod.clone().build().pack().unwrap();
+            assert_eq!(sniff(&bin).unwrap().compression, compression);
+            assert_eq!(bin.len() < 64 * 1024, compression.codec != Codec::None);
+
+            let tmod2 = ForgeMod::from_bytes(&*bin).unwrap();
+            assert_eq!(tmod2.compression, compression);
+            assert_eq!(_tmod.build(), tmod2);
+
+            let mut data = Vec::new();
+            open_include(Cursor::new(&bin), "Libs/a.dll").unwrap().read_to_end(&mut data).unwrap();
+            assert_eq!(data, vec![0xAA; 2048]);
+        }
+
+        assert_eq!(Compression::new(Codec::Xz, 200).level, 9);
+        assert_eq!(Compression::new(Codec::Zstd, 0).level, 1);
+    }
+
+    #[test]
+    fn test_incompressible_entries() {
+        // noise larger than the decoder buffers, the end of each compressed stream must still be consumed
+        let noise: Vec<u8> = (0..155_592u64)
+            .scan(7u64, |state, _| {
+                *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                Some((*state >> 56) as u8)
+            })
+            .collect();
+
+        for compression in [Compression::new(Codec::Xz, 1), Compression::new(Codec::Zstd, 1)] {
+            let mut _tmod = ModBuilder::new_mod_raw(
+                ManifestBuilder::new_mod(
+                    "pp".to_string(),
+                    Version::new(0, 1, 2),
+                    VersionReq::parse("=1.23.4").unwrap(),
+                )
+                .build(),
+                noise.clone(),
+            );
+            _tmod.compression(compression);
+            _tmod.includes(
+                IncludeDataBuilder::new()
+                    .add_raw("Libs/a.dll".to_string(), noise[..40 * 1024].to_vec())
+                    .add_raw("Libs/b.dll".to_string(), vec![0xBB; 1024])
+                    .clone()
+                    .build(),
+            );
+
+            let bin = _tmod.clone().build().pack().unwrap();
+            assert_eq!(ForgeMod::from_bytes(&*bin).unwrap(), _tmod.clone().build());
+            assert!(matches!(unpack_v1_forgemod(&*bin).unwrap(), ForgeModTypes::Mod(_)));
+        }
+    }
+
+    #[test]
+    fn test_checksums() {
+        let mut _manifest = ManifestBuilder::new_mod(
+            "pp".to_string(),
+            Version::new(0, 1, 2),
+            VersionReq::parse("=1.23.4").unwrap(),
+        );
+        _manifest.description("checked".to_string());
+        let mut _tmod = ModBuilder::new_mod_raw(_manifest.build(), vec![0xFF; 16]);
+        _tmod.compression(Compression::new(Codec::None, 0));
+        _tmod.includes(IncludeDataBuilder::new().add_raw("Libs/a.dll".to_string(), vec![0xAA; 16]).clone().build());
+        let _tmod = _tmod.build();
+        assert_eq!(_tmod.checksums.entries.len(), 2);
+
+        // stored uncompressed, so the blobs can be found and flipped in place
+        let bin = _tmod.pack().unwrap().to_vec();
+        let corrupt = |needle: &[u8]| {
+            let mut bin = bin.clone();
+            let at = bin.windows(needle.len()).position(|w| w == needle).unwrap();
+            bin[at] ^= 0x01;
+            bin
+        };
+
+        let err = unpack_v1_forgemod(&*corrupt(&[0xAA; 16])).unwrap_err();
+        assert_eq!(err.downcast_ref::<ChecksumError>().unwrap().entry, "Libs/a.dll");
+
+        let err = unpack_v1_forgemod(&*corrupt(&[0xFF; 16])).unwrap_err();
+        assert_eq!(err.downcast_ref::<ChecksumError>().unwrap().entry, "artifact");
+
+        let err = peek_manifest(&*corrupt(b"checked")).unwrap_err();
+        assert_eq!(err.downcast_ref::<ChecksumError>().unwrap().entry, "manifest");
+
+        let mut data = Vec::new();
+        let err = open_include(Cursor::new(corrupt(&[0xAA; 16])), "Libs/a.dll")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap_err();
+        assert_eq!(err.get_ref().unwrap().downcast_ref::<ChecksumError>().unwrap().entry, "Libs/a.dll");
+
+        // changing a built mod needs fresh checksums before it can be packed again
+        let mut changed = ForgeMod::from_bytes(&*bin).unwrap();
+        assert_eq!(changed, _tmod);
+        changed.data.artifact_data.push(0x00);
+        assert!(changed.pack().is_err());
+        changed.update_checksums();
+        assert_eq!(ForgeMod::from_bytes(&*changed.pack().unwrap()).unwrap(), changed);
+    }
+
+    #[test]
+    fn test_signing() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let other = SigningKey::from_bytes(&[8; 32]);
+
+        let mut _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF, 0xFF],
+        )
+        .build();
+        assert_eq!(_tmod.verify(&key.verifying_key()), Err(SignatureError::Unsigned));
+
+        _tmod.sign(&key);
+        let mut tmod2 = ForgeMod::from_bytes(&*_tmod.pack().unwrap()).unwrap();
+        assert_eq!(tmod2, _tmod);
+        assert_eq!(tmod2.verify(&key.verifying_key()), Ok(()));
+        assert_eq!(tmod2.verify(&other.verifying_key()), Err(SignatureError::WrongKey));
+
+        // the signature does not depend on how the package is compressed
+        tmod2.compression = Compression::fast();
+        let mut tmod3 = ForgeMod::from_bytes(&*tmod2.pack().unwrap()).unwrap();
+        assert_eq!(tmod3, tmod2);
+        assert_eq!(tmod3.verify(&key.verifying_key()), Ok(()));
+
+        tmod3.data.artifact_data.push(0x00);
+        assert_eq!(tmod3.verify(&key.verifying_key()), Err(SignatureError::Invalid));
+    }
+
+    #[test]
+    fn test_unpack_limits() {
+        let mut _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF; 0x1234],
+        );
+        _tmod.includes(
+            IncludeDataBuilder::new()
+                .add_raw("Libs/a.dll".to_string(), vec![0xAA; 1500])
+                .add_raw("Libs/b.dll".to_string(), vec![0xBB; 1500])
+                .clone()
+                .build(),
+        );
+        let _tmod = _tmod.build();
+        let bin = _tmod.pack().unwrap();
+
+        let unpack = |bin: &[u8], limits: UnpackLimits| {
+            *unpack_v1_forgemod_with_limits(bin, &limits).unwrap_err().downcast::<LimitError>().unwrap()
+        };
+        let limits = UnpackLimits::default();
+
+        assert_eq!(
+            unpack(&bin, UnpackLimits { max_entry_size: 4096, ..limits }),
+            LimitError::EntrySize { entry: "artifact".into(), limit: 4096 }
+        );
+        assert_eq!(
+            unpack(&bin, UnpackLimits { max_include_count: 1, ..limits }),
+            LimitError::IncludeCount { limit: 1 }
+        );
+        assert_eq!(
+            unpack(&bin, UnpackLimits { max_total_size: 6000, ..limits }),
+            LimitError::TotalSize { limit: 6000 }
+        );
+        assert_eq!(
+            unpack(&bin, UnpackLimits { max_path_len: 4, ..limits }),
+            LimitError::PathLength { limit: 4 }
+        );
+        assert!(unpack_v1_forgemod_with_limits(&*bin, &UnpackLimits::unlimited()).is_ok());
+
+        // a forged length prefix in a legacy package must not be allocated
+        let mut legacy = bincode::serialize(&_tmod).unwrap();
+        let prefix = (0x1234u64).to_le_bytes();
+        let at = legacy.windows(8).position(|w| w == prefix).unwrap();
+        legacy[at..at + 8].copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert_eq!(unpack(&legacy, limits), LimitError::TotalSize { limit: limits.max_total_size });
+
+        let manifest = br#"{
+            "_id": "pp",
+            "manifest_version": 1,
+            "type": "mod",
+            "name": "pp",
+            "description": "",
+            "website": "",
+            "version": "0.1.2",
+            "game_version": "=1.23.4",
+            "category": "",
+            "includes": [{ "bs_dest": "Libs/a-very-long-path.dll", "local_src": "a.dll" }],
+            "depends": [],
+            "conflicts": []
+        }"#;
+        assert!(parse_v1_forgemanifest(&manifest[..]).is_ok());
+        let err = parse_v1_forgemanifest_with_limits(&manifest[..], &UnpackLimits { max_path_len: 16, ..limits }).unwrap_err();
+        assert_eq!(*err.downcast::<LimitError>().unwrap(), LimitError::PathLength { limit: 16 });
+    }
+
+    #[test]
+    fn test_shared_unpack() {
+        let mut _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xAB; 4096],
+        );
+        _tmod.includes(IncludeDataBuilder::new().add_raw("Libs/a.dll".to_string(), vec![0xAA; 2048]).clone().build());
+
+        // uncompressed entries point into the packed bytes
+        _tmod.compression(Compression::new(Codec::None, 0));
+        let bin = _tmod.clone().build().pack().unwrap();
+        let packed = bin.as_ptr_range();
+        let tmod2 = match unpack_v1_forgemod_shared(bin.clone()).unwrap() {
+            ForgeModTypes::Mod(m) => m,
+            _ => panic!("expected a mod"),
+        };
+        assert!(packed.contains(&tmod2.data.artifact_data.as_ptr()));
+        assert!(packed.contains(&tmod2.data.includes_data[0].data.as_ptr()));
+        assert_eq!(&tmod2.data.artifact_data[..], &[0xAB; 4096][..]);
+        assert_eq!(&tmod2.data.includes_data[0].data[..], &[0xAA; 2048][..]);
+        assert_eq!(tmod2.pack().unwrap(), bin);
+
+        // compressed ones are decompressed into their own buffers
+        _tmod.compression(Compression::default());
+        let bin = _tmod.clone().build().pack().unwrap();
+        let tmod3 = match unpack_v1_forgemod_shared(bin.clone()).unwrap() {
+            ForgeModTypes::Mod(m) => m,
+            _ => panic!("expected a mod"),
+        };
+        assert!(!bin.as_ptr_range().contains(&tmod3.data.artifact_data.as_ptr()));
+        assert_eq!(&tmod3.data.artifact_data[..], &[0xAB; 4096][..]);
+        assert_eq!(tmod3.pack().unwrap(), bin);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let dll = vec![0xAA; 16 * 1024];
+        let mut _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xAB; 1024],
+        );
+        _tmod.compression(Compression::new(Codec::None, 0));
+        _tmod.includes(
+            IncludeDataBuilder::new()
+                .add_raw("Libs/a.dll".to_string(), dll.clone())
+                .add_raw("Plugins/a.dll".to_string(), dll.clone())
+                .add_raw("Libs/b.dll".to_string(), vec![0xBB; 1024])
+                .clone()
+                .build(),
+        );
+
+        let bin = _tmod.clone().build().pack().unwrap();
+        assert!(bin.len() < 2 * dll.len());
+
+        let tmod2 = ForgeMod::from_bytes(&*bin).unwrap();
+        assert_eq!(_tmod.clone().build(), tmod2);
+
+        let index = read_index(Cursor::new(&bin)).unwrap();
+        let offset = |dest: &str| index.iter().find(|e| e.path == dest).unwrap().offset;
+        assert_eq!(offset("Libs/a.dll"), offset("Plugins/a.dll"));
+        assert_ne!(offset("Libs/a.dll"), offset("Libs/b.dll"));
+
+        let mut data = Vec::new();
+        open_include(Cursor::new(&bin), "Plugins/a.dll").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, dll);
+
+        // both includes share one buffer
+        let tmod3 = match unpack_v1_forgemod_shared(bin.clone()).unwrap() {
+            ForgeModTypes::Mod(m) => m,
+            _ => panic!("expected a mod"),
+        };
+        assert_eq!(tmod3.data.includes_data[0].data.as_ptr(), tmod3.data.includes_data[2].data.as_ptr());
+        assert_eq!(tmod3.pack().unwrap(), bin);
+
+        _tmod.compression(Compression::default());
+        let bin = _tmod.clone().build().pack().unwrap();
+        let tmod4 = match unpack_v1_forgemod_shared(bin).unwrap() {
+            ForgeModTypes::Mod(m) => m,
+            _ => panic!("expected a mod"),
+        };
+        assert_eq!(tmod4.data.includes_data[0].data.as_ptr(), tmod4.data.includes_data[2].data.as_ptr());
+    }
+
+    #[test]
+    fn test_delta() {
+        // noise, so the dll does not compress away
+        let dll: Vec<u8> = (0..64 * 1024u64)
+            .scan(1u64, |state, _| {
+                *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                Some((*state >> 56) as u8)
+            })
+            .collect();
+        let mut patched = dll.clone();
+        patched[1234] ^= 0xFF;
+
+        let mut old = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            dll.clone(),
+        );
+        old.includes(
+            IncludeDataBuilder::new()
+                .add_raw("Libs/a.dll".to_string(), dll.iter().rev().copied().collect())
+                .add_raw("Libs/b.dll".to_string(), vec![0xBB; 1024])
+                .clone()
+                .build(),
+        );
+        let old = old.build();
+
+        let mut new = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 3),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            patched,
+        );
+        new.includes(
+            IncludeDataBuilder::new()
+                .add_raw("Libs/a.dll".to_string(), dll.iter().rev().copied().collect())
+                .add_raw("Libs/c.dll".to_string(), vec![0xBB; 1024])
+                .add_raw("Libs/d.dll".to_string(), vec![0xDD; 1024])
+                .clone()
+                .build(),
+        );
+        let new = new.build();
+
+        let delta = ForgeMod::diff(&old, &new);
+        assert!(matches!(delta.entries[0].patch, Patch::Diff { .. }));
+        assert!(matches!(delta.entries[1].patch, Patch::Base(_)));
+        assert!(matches!(delta.entries[2].patch, Patch::Base(_)));
+        assert!(matches!(delta.entries[3].patch, Patch::Full(_)));
+
+        let bin = delta.pack().unwrap();
+        assert!(bin.len() < new.pack().unwrap().len() / 10);
+        let delta = DeltaPackage::from_bytes(&*bin).unwrap();
+        assert_eq!(delta.apply(&old).unwrap(), new);
+        assert!(delta.apply(&old).unwrap().pack().is_ok());
+
+        assert_eq!(delta.apply(&new).unwrap_err(), DeltaError::WrongBase);
+
+        let mut broken = delta.clone();
+        broken.entries[3].patch = Patch::Full(vec![0xEE; 1024]);
+        assert_eq!(
+            broken.apply(&old).unwrap_err(),
+            DeltaError::Corrupt {
+                entry: "Libs/d.dll".into()
+            }
+        );
+
+        let mut broken = delta;
+        broken.manifest.inner.version = Version::new(0, 1, 4);
+        assert_eq!(broken.apply(&old).unwrap_err(), DeltaError::Mismatch);
+    }
+
+    #[test]
+    fn test_parent_modules() {
+        let module = |name: &str, artifact: u8| {
+            let mut _tmod = ModBuilder::new_module_raw(ManifestBuilder::new_module(name.to_string()).build(), vec![artifact; 64]);
+            _tmod.includes(IncludeDataBuilder::new().add_raw("Libs/a.dll".to_string(), vec![artifact; 32]).clone().build());
+            _tmod.build()
+        };
+
+        let mut _manifest = ManifestBuilder::new_module_parent(
+            "pp".to_string(),
+            Version::new(0, 1, 2),
+            VersionReq::parse("=1.23.4").unwrap(),
+        );
+        _manifest.modules(vec![PathBuf::from("modules/extra-a"), PathBuf::from("modules/extra-b.json")]);
+        let mut _tmod = ModBuilder::new_module_parent(_manifest.build());
+
+        assert_eq!(
+            _tmod.modules(vec![module("Extra A", 0xAA)]).unwrap_err(),
+            ModuleError::Missing { id: "extra-b".into() }
+        );
+        assert_eq!(
+            _tmod.modules(vec![module("Extra A", 0xAA), module("Extra A", 0xAA)]).unwrap_err(),
+            ModuleError::Duplicate { id: "extra-a".into() }
+        );
+        _tmod.modules(vec![module("Extra A", 0xAA), module("Extra B", 0xBB)]).unwrap();
+
+        let bin = _tmod.clone().build().pack().unwrap();
+        let tmod2 = match unpack_v1_forgemod(&*bin).unwrap() {
+            ForgeModTypes::Parent(m) => m,
+            m => panic!("expected a parent, got {}", m),
+        };
+        assert_eq!(_tmod.clone().build(), tmod2);
+        assert_eq!(tmod2.data.module("extra-b").unwrap(), &module("Extra B", 0xBB));
+        assert!(tmod2.data.module("extra-c").is_none());
+
+        let mut data = Vec::new();
+        open_include(Cursor::new(&bin), "extra-b/Libs/a.dll").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![0xBB; 32]);
+
+        // the listed modules are checked again on unpack
+        let mut tmod3 = _tmod.build();
+        tmod3.manifest.inner.modules.pop();
+        tmod3.update_checksums();
+        let err = unpack_v1_forgemod(&*tmod3.pack().unwrap()).unwrap_err();
+        assert_eq!(*err.downcast::<ModuleError>().unwrap(), ModuleError::Unlisted { id: "extra-b".into() });
+
+        // ids can have dots, only a .json extension is taken off
+        let mut dotted = module("My", 0xCC);
+        dotted.manifest._id = "my.module".into();
+        dotted.update_checksums();
+        let embed = |path: &str| {
+            let mut _manifest = ManifestBuilder::new_module_parent(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            );
+            _manifest.modules(vec![PathBuf::from(path)]);
+            ModBuilder::new_module_parent(_manifest.build()).modules(vec![dotted.clone()]).map(|_| ())
+        };
+        assert_eq!(embed("modules/my.module"), Ok(()));
+        assert_eq!(embed("modules/my.module.json"), Ok(()));
+        for path in ["modules/..", "modules/.json", "/"] {
+            assert_eq!(embed(path), Err(ModuleError::InvalidPath { path: path.into() }));
+        }
+
+        // packages from before the container header list their modules without embedding them
+        let legacy = bincode::serialize(&(1u32, "parent", &tmod3.manifest)).unwrap();
+        let tmod4 = match unpack_v1_forgemod(&*legacy).unwrap() {
+            ForgeModTypes::Parent(m) => m,
+            m => panic!("expected a parent, got {}", m),
+        };
+        assert_eq!(tmod4.manifest, tmod3.manifest);
+        assert!(tmod4.data.modules.is_empty());
+    }
+
+    #[test]
+    fn test_unpack_any_version() {
+        let mut _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF, 0xFF],
+        )
+        .build();
+
+        let bin = _tmod.pack().unwrap();
+        match unpack_forgemod(&*bin).unwrap() {
+            ForgeModTypes::Mod(m) => assert_eq!(m, _tmod),
+            m => panic!("expected a mod, got {}", m),
+        }
+        let versioned = read_versioned_forgemod(&*bin, &UnpackLimits::default()).unwrap();
+        assert_eq!(versioned.format_version(), 1);
+        assert!(matches!(versioned, VersionedForgeMod::V1(ForgeModTypes::Mod(_))));
+
+        // packages from before the container header are migrated as well
+        let legacy = bincode::serialize(&_tmod).unwrap();
+        let legacy = xz2::write::XzEncoder::new(legacy, 9).finish().unwrap();
+        assert!(matches!(unpack_forgemod(&*legacy).unwrap(), ForgeModTypes::Mod(m) if m.data == _tmod.data));
+
+        _tmod.format_version = 2;
+        let err = unpack_forgemod(&*_tmod.pack().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported format version 2");
+
+        let manifest = br#"{"_id": "pp", "manifest_version": 1, "type": "lib", "name": "pp", "description": "",
+            "website": "", "version": "0.1.2", "game_version": "=1.23.4", "category": "", "includes": [],
+            "pre_exec": null, "post_exec": null, "depends": [], "conflicts": []}"#;
+        assert!(matches!(parse_forgemanifest(&manifest[..]).unwrap(), ForgeManifestTypes::Lib(_)));
+        let manifest = br#"{"_id": "pp", "manifest_version": 7, "type": "lib"}"#;
+        let err = parse_forgemanifest(&manifest[..]).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported manifest version 7");
+    }
+
+    /// `manifest::Mod` as a later version of the library might extend it.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct NewerMod {
+        #[serde(flatten)]
+        inner: manifest::Mod,
+        license: Option<String>,
+    }
+
+    impl ManifestComponent for NewerMod {}
+
+    /// `manifest::Module` extended the same way.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct NewerModule {
+        #[serde(flatten)]
+        inner: manifest::Module,
+        license: Option<String>,
+    }
+
+    impl ManifestComponent for NewerModule {}
+
+    #[test]
+    fn test_manifest_fields() {
+        let _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF, 0xFF],
+        )
+        .build();
+
+        // a newer package still unpacks, the unknown field is skipped
+        let mut newer = ForgeMod {
+            format_version: _tmod.format_version,
+            kind: _tmod.kind.clone(),
+            manifest: ForgeManifestSafe {
+                _id: _tmod.manifest._id.clone(),
+                manifest_version: _tmod.manifest.manifest_version,
+                _type: _tmod.manifest._type.clone(),
+                inner: NewerMod {
+                    inner: _tmod.manifest.inner.clone(),
+                    license: Some("MIT".into()),
+                },
+                _marker: _tmod.manifest._marker,
+            },
+            data: _tmod.data.clone(),
+            compression: _tmod.compression,
+            checksums: _tmod.checksums.clone(),
+            signature: None,
+            raw_manifest: _tmod.raw_manifest.clone(),
+            _marker: _tmod._marker,
+        };
+        newer.update_checksums();
+        let tmod2 = match unpack_v1_forgemod(&*newer.pack().unwrap()).unwrap() {
+            ForgeModTypes::Mod(m) => m,
+            m => panic!("expected a mod, got {}", m),
+        };
+        assert_eq!(tmod2.manifest, _tmod.manifest);
+
+        // flattened manifests sign and diff like any other
+        let key = SigningKey::from_bytes(&[7; 32]);
+        newer.sign(&key);
+        assert_eq!(newer.verify(&key.verifying_key()), Ok(()));
+        let delta = ForgeMod::diff(&newer, &newer);
+        assert_eq!(delta.apply(&newer).unwrap().manifest, newer.manifest);
+
+        // an older reader verifies and repacks the manifest it was given, unknown field included
+        let signed = newer.pack().unwrap();
+        let mut older_reader = ForgeMod::<ManifestV1, manifest::Mod, data::Mod>::from_bytes(&*signed).unwrap();
+        assert_eq!(older_reader.verify(&key.verifying_key()), Ok(()));
+        let repacked = older_reader.pack().unwrap();
+        let newer2 = ForgeMod::<ManifestV1, NewerMod, data::Mod>::from_bytes(&*repacked).unwrap();
+        assert_eq!(newer2.manifest, newer.manifest);
+        assert_eq!(newer2.verify(&key.verifying_key()), Ok(()));
+
+        // changing the manifest still needs new checksums, and drops what the reader does not know
+        older_reader.manifest.inner.description = "changed".into();
+        assert!(older_reader.pack().unwrap_err().to_string().contains("checksum mismatch"));
+        assert_eq!(older_reader.verify(&key.verifying_key()), Err(SignatureError::Invalid));
+        older_reader.update_checksums();
+        let newer3 = ForgeMod::<ManifestV1, NewerMod, data::Mod>::from_bytes(&*older_reader.pack().unwrap()).unwrap();
+        assert_eq!(newer3.manifest.inner.license, None);
+
+        // embedded modules keep the manifest they were given through a parent as well
+        let module = ModBuilder::new_module_raw(ManifestBuilder::new_module("Extra A".to_string()).build(), vec![0xAA; 64]).build();
+        let mut newer_module = ForgeMod {
+            format_version: module.format_version,
+            kind: module.kind.clone(),
+            manifest: ForgeManifestSafe {
+                _id: module.manifest._id.clone(),
+                manifest_version: module.manifest.manifest_version,
+                _type: module.manifest._type.clone(),
+                inner: NewerModule {
+                    inner: module.manifest.inner.clone(),
+                    license: Some("MIT".into()),
+                },
+                _marker: module.manifest._marker,
+            },
+            data: module.data.clone(),
+            compression: module.compression,
+            checksums: module.checksums.clone(),
+            signature: None,
+            raw_manifest: module.raw_manifest.clone(),
+            _marker: module._marker,
+        };
+        newer_module.update_checksums();
+        newer_module.sign(&key);
+        let embedded = ForgeMod::<ManifestV1, manifest::Module, data::Module>::from_bytes(&*newer_module.pack().unwrap()).unwrap();
+
+        let mut _manifest = ManifestBuilder::new_module_parent(
+            "pp".to_string(),
+            Version::new(0, 1, 2),
+            VersionReq::parse("=1.23.4").unwrap(),
+        );
+        _manifest.modules(vec![PathBuf::from("modules/extra-a")]);
+        let mut parent = ModBuilder::new_module_parent(_manifest.build());
+        parent.modules(vec![embedded]).unwrap();
+        let parent = match unpack_v1_forgemod(&*parent.build().pack().unwrap()).unwrap() {
+            ForgeModTypes::Parent(m) => m,
+            m => panic!("expected a parent, got {}", m),
+        };
+        let module2 = parent.data.module("extra-a").unwrap();
+        assert_eq!(module2.verify(&key.verifying_key()), Ok(()));
+        let newer_module2 = ForgeMod::<ManifestV1, NewerModule, data::Module>::from_bytes(&*module2.pack().unwrap()).unwrap();
+        assert_eq!(newer_module2.manifest, newer_module.manifest);
+
+        // and a newer reader sees the field as missing in older packages
+        let older = ForgeMod::<ManifestV1, NewerMod, data::Mod>::from_bytes(&*_tmod.pack().unwrap()).unwrap();
+        assert_eq!(older.manifest.inner.inner, _tmod.manifest.inner);
+        assert_eq!(older.manifest.inner.license, None);
+    }
+
+    #[test]
+    fn test_reproducible_pack() {
+        let build = |includes: &[(&str, u8)]| {
+            let mut _tmod = ModBuilder::new_mod_raw(
+                ManifestBuilder::new_mod(
+                    "pp".to_string(),
+                    Version::new(0, 1, 2),
+                    VersionReq::parse("=1.23.4").unwrap(),
+                )
+                .build(),
+                vec![0xFF; 1024],
+            );
+            let mut builder = IncludeDataBuilder::new();
+            for (dest, byte) in includes {
+                builder.add_raw(dest.to_string(), vec![*byte; 512]);
+            }
+            _tmod.includes(builder.build());
+            _tmod
+        };
+
+        let includes = [("Libs/a.dll", 0xAA), ("Plugins/b.dll", 0xBB), ("Libs/c.dll", 0xCC), ("Libs/a.dll", 0xDD)];
+        let mut reordered = includes;
+        reordered.reverse();
+
+        for compression in [Compression::default(), Compression::fast(), Compression::new(Codec::None, 0)] {
+            let mut a = build(&includes);
+            let mut b = build(&reordered);
+            a.compression(compression);
+            b.compression(compression);
+            assert_eq!(a.build().pack().unwrap(), b.build().pack().unwrap());
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_async_io() {
+        use crate::structs::async_io::{peek_manifest_async, unpack_forgemod_async, unpack_forgemod_async_with_limits};
+
+        let mut _tmod = ModBuilder::new_mod_raw(
+            ManifestBuilder::new_mod(
+                "pp".to_string(),
+                Version::new(0, 1, 2),
+                VersionReq::parse("=1.23.4").unwrap(),
+            )
+            .build(),
+            vec![0xFF; 4096],
+        );
+        // the second include is stored once, as a shared blob
+        _tmod.includes(
+            IncludeDataBuilder::new()
+                .add_raw("Libs/a.dll".to_string(), vec![0xAA; 2048])
+                .add_raw("Libs/b.dll".to_string(), vec![0xAA; 2048])
+                .clone()
+                .build(),
+        );
+        let _tmod = _tmod.build();
+
+        let mut bin = Vec::new();
+        _tmod.pack_to_async(&mut bin).await.unwrap();
+        assert_eq!(bin, _tmod.pack().unwrap());
+
+        match unpack_forgemod_async(Cursor::new(bin.clone())).await.unwrap() {
+            ForgeModTypes::Mod(m) => assert_eq!(m, _tmod),
+            m => panic!("expected a mod, got {}", m),
+        }
+
+        let manifest = match peek_manifest_async(Cursor::new(bin.clone())).await.unwrap() {
+            ForgeManifestTypes::Mod(m) => m,
+            m => panic!("expected a mod manifest, got {}", m),
+        };
+        assert_eq!(manifest.inner, _tmod.manifest.inner);
+
+        let limits = UnpackLimits { max_entry_size: 1024, ..UnpackLimits::default() };
+        let err = unpack_forgemod_async_with_limits(Cursor::new(bin), &limits).await.unwrap_err();
+        assert!(err.downcast_ref::<LimitError>().is_some());
+    }
+
+    /// Fresh directory for a test, removed again when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("forge-lib-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, path: &str, data: &[u8]) -> PathBuf {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, data).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Mod `name` at version `0.1.<version>` with these includes, its artifact differs per version.
+    fn build_mod(name: &str, version: u64, includes: &[(&str, &[u8])]) -> ForgeModTypes {
+        build_mod_with_hook(name, version, includes, None)
+    }
+
+    fn build_mod_with_hook(name: &str, version: u64, includes: &[(&str, &[u8])], post_exec: Option<&str>) -> ForgeModTypes {
+        let mut manifest =
+            ManifestBuilder::new_mod(name.to_string(), Version::new(0, 1, version), VersionReq::parse("=1.23.4").unwrap());
+        manifest.post_exec(post_exec.map(PathBuf::from));
+        let mut _tmod = ModBuilder::new_mod_raw(manifest.build(), vec![0xFF, version as u8]);
+        let mut builder = IncludeDataBuilder::new();
+        for (dest, data) in includes {
+            builder.add_raw(dest.to_string(), data.to_vec());
+        }
+        _tmod.includes(builder.build());
+        ForgeModTypes::Mod(_tmod.build())
+    }
+
+    #[test]
+    fn test_add_dir() {
+        let dir = TempDir::new("add-dir");
+        for path in ["Libs/a.dll", "Libs/b.dll", "Libs/b.pdb", "UserData/cfg/x.json", "readme.md"] {
+            dir.write(path, path.as_bytes());
+        }
+
+        let filters = IncludeFilters {
+            include: vec!["Libs/**".into(), "UserData/**".into()],
+            exclude: vec!["**/*.pdb".into()],
+        };
+        let includes = IncludeDataBuilder::new().add_dir("Plugins/".into(), dir.0.clone(), &filters).unwrap().clone().build();
+        let dests: Vec<_> = includes.iter().map(|i| i.dest.as_str()).collect();
+        assert_eq!(dests, ["Plugins/Libs/a.dll", "Plugins/Libs/b.dll", "Plugins/UserData/cfg/x.json"]);
+        assert_eq!(includes[2].data, b"UserData/cfg/x.json");
+
+        let bad = IncludeFilters {
+            include: vec!["Libs/[".into()],
+            ..Default::default()
+        };
+        assert!(IncludeDataBuilder::new().add_dir("".into(), dir.0.clone(), &bad).is_err());
+
+        // manifest includes expand directories the same way
+        let mut manifest_includes = IncludeBuilder::new();
+        manifest_includes.add("UserData".into(), dir.0.join("UserData")).add("Libs/c.dll".into(), dir.0.join("Libs/a.dll"));
+        let mut builder = IncludeDataBuilder::new();
+        for include in manifest_includes.build() {
+            builder.add_include(&include).unwrap();
+        }
+        let includes = builder.build();
+        let dests: Vec<_> = includes.iter().map(|i| i.dest.as_str()).collect();
+        assert_eq!(dests, ["UserData/cfg/x.json", "Libs/c.dll"]);
+        assert_eq!(includes[1].data, b"Libs/a.dll");
+
+        // links are reported, not followed
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.0.join("Libs/a.dll"), dir.0.join("Libs/link.dll")).unwrap();
+            let mut builder = IncludeDataBuilder::new();
+            builder.add_dir("".into(), dir.0.join("Libs"), &IncludeFilters::default()).unwrap();
+            let skipped: Vec<_> = builder.report().skipped.iter().map(|s| (s.path.as_str(), &s.reason)).collect();
+            assert_eq!(skipped[1], ("link.dll", &SkipReason::NotAFile));
+            let dests: Vec<_> = builder.build().into_iter().map(|i| i.dest).collect();
+            assert_eq!(dests, ["a.dll", "b.dll"]);
+        }
+    }
+
+    #[test]
+    fn test_forgeignore() {
+        let dir = TempDir::new("forgeignore");
+        for path in [
+            "Mods/a.dll",
+            "Mods/a.pdb",
+            "Mods/keep.pdb",
+            "obj/Debug/a.o",
+            "notes.txt",
+            "notes.txt~",
+            "cfg/local.json",
+            "cfg/shared/local.json",
+        ] {
+            dir.write(path, path.as_bytes());
+        }
+        dir.write(".forgeignore", b"# docs stay local\n*.txt\n!keep.pdb\n");
+        dir.write("cfg/.forgeignore", b"/local.json\n");
+
+        let mut builder = IncludeDataBuilder::new();
+        builder.add_dir("".into(), dir.0.clone(), &IncludeFilters::default()).unwrap();
+        let skipped: Vec<_> = builder.report().skipped.iter().map(|s| (s.path.as_str(), &s.reason)).collect();
+        assert_eq!(
+            skipped,
+            [
+                (".forgeignore", &SkipReason::Ignored { file: None, pattern: ".forgeignore".into() }),
+                ("Mods/a.pdb", &SkipReason::Ignored { file: None, pattern: "*.pdb".into() }),
+                ("cfg/.forgeignore", &SkipReason::Ignored { file: None, pattern: ".forgeignore".into() }),
+                (
+                    "cfg/local.json",
+                    &SkipReason::Ignored {
+                        file: Some("cfg/.forgeignore".into()),
+                        pattern: "/local.json".into()
+                    }
+                ),
+                (
+                    "notes.txt",
+                    &SkipReason::Ignored {
+                        file: Some(".forgeignore".into()),
+                        pattern: "*.txt".into()
+                    }
+                ),
+                ("notes.txt~", &SkipReason::Ignored { file: None, pattern: "*~".into() }),
+                ("obj", &SkipReason::Ignored { file: None, pattern: "obj/".into() }),
+            ]
+        );
+        assert!(builder.report().to_string().contains("skipped Mods/a.pdb: ignored by default rule `*.pdb`\n"));
+
+        let dests: Vec<_> = builder.build().into_iter().map(|i| i.dest).collect();
+        assert_eq!(dests, ["Mods/a.dll", "Mods/keep.pdb", "cfg/shared/local.json"]);
+    }
+
+    #[test]
+    fn test_file_attrs() {
+        let dir = TempDir::new("file-attrs");
+        let script = dir.write("run.sh", b"#!/bin/sh\n");
+        let config = dir.write("config.json", b"{}");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        FileAttrs { mode: None, mtime: Some(1_600_000_000) }.apply(&config).unwrap();
+
+        let mut builder = IncludeDataBuilder::new();
+        builder.add("run.sh".into(), script.clone()).unwrap();
+        builder.keep_mtimes(true).add("config.json".into(), config.clone()).unwrap();
+        let includes = builder.build();
+        // nothing depends on who built it or when, unless asked for
+        #[cfg(unix)]
+        assert_eq!(includes[0].attrs, FileAttrs { mode: Some(0o755), mtime: None });
+        assert_eq!(includes[1].attrs, FileAttrs { mode: None, mtime: Some(1_600_000_000) });
+
+        let build = |includes: Vec<data::IncludeData>| {
+            let mut _tmod = ModBuilder::new_mod_raw(
+                ManifestBuilder::new_mod(
+                    "pp".to_string(),
+                    Version::new(0, 1, 2),
+                    VersionReq::parse("=1.23.4").unwrap(),
+                )
+                .build(),
+                vec![0xFF, 0xFF],
+            );
+            _tmod.includes(includes);
+            _tmod.build()
+        };
+        let plain = build(includes.iter().map(|i| data::IncludeData { attrs: FileAttrs::default(), ..i.clone() }).collect());
+        let mut _tmod = build(includes);
+
+        let bin = _tmod.pack().unwrap();
+        assert_eq!(ForgeMod::from_bytes(&*bin).unwrap(), _tmod);
+        let index = read_index(Cursor::new(&bin)).unwrap();
+        assert_eq!(index.iter().map(|e| e.attrs.mtime).collect::<Vec<_>>(), [None, Some(1_600_000_000), None]);
+
+        // attributes are covered by the signature
+        let key = SigningKey::from_bytes(&[7; 32]);
+        _tmod.sign(&key);
+        let mut tampered = _tmod.clone();
+        tampered.data.includes_data[1].attrs.mode = Some(0o4755);
+        assert_eq!(tampered.verify(&key.verifying_key()), Err(SignatureError::Invalid));
+
+        // and carried by deltas
+        let delta = ForgeMod::diff(&plain, &_tmod);
+        assert!(delta.entries.iter().skip(1).all(|e| matches!(e.patch, Patch::Base(_))));
+        assert_eq!(delta.apply(&plain).unwrap(), _tmod);
+
+        // includes are sorted, config.json comes first
+        for include in &_tmod.data.includes_data {
+            let out = dir.write(&format!("out/{}", include.dest), &include.data);
+            include.attrs.apply(&out).unwrap();
+        }
+        let mtime = std::fs::metadata(dir.0.join("out/config.json")).unwrap().modified().unwrap();
+        assert_eq!(mtime.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(), 1_600_000_000);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.0.join("out/run.sh")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+
+            // setuid, setgid and sticky never make it onto the file
+            let out = dir.write("out/suid.sh", b"#!/bin/sh\n");
+            FileAttrs { mode: Some(0o7777), mtime: None }.apply(&out).unwrap();
+            assert_eq!(std::fs::metadata(&out).unwrap().permissions().mode() & 0o7777, 0o777);
+        }
+
+        let out = dir.write("out/far.json", b"{}");
+        let err = FileAttrs { mode: None, mtime: Some(u64::MAX) }.apply(&out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_install() {
+        let game = TempDir::new("install");
+        let _tmod = build_mod("pp", 2, &[("./Libs/dep.dll", &[1]), ("UserData/pp/config.json", &[2])]);
+
+        let receipt = _tmod.install(&game.0).unwrap();
+        let installed: Vec<_> = receipt.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(installed, ["Plugins/pp.dll", "Libs/dep.dll", "UserData/pp/config.json"]);
+        assert_eq!(std::fs::read(game.0.join("Plugins/pp.dll")).unwrap(), [0xFF, 2]);
+        assert_eq!(std::fs::read(game.0.join("Libs/dep.dll")).unwrap(), [1]);
+        assert_eq!(std::fs::read(game.0.join("UserData/pp/config.json")).unwrap(), [2]);
+
+        let install = |dest: &str| {
+            let err = build_mod("evil", 2, &[(dest, &[3])]).install(&game.0).unwrap_err();
+            *err.into_inner().unwrap().downcast::<InstallError>().unwrap()
+        };
+        assert_eq!(install("/etc/passwd"), InstallError::Absolute { dest: "/etc/passwd".into() });
+        assert_eq!(install("C:\\Windows\\x.dll"), InstallError::Absolute { dest: "C:\\Windows\\x.dll".into() });
+        assert_eq!(install("../x.dll"), InstallError::Traversal { dest: "../x.dll".into() });
+        assert_eq!(install("Plugins/..\\..\\x.dll"), InstallError::Traversal { dest: "Plugins/..\\..\\x.dll".into() });
+        assert_eq!(install("./"), InstallError::Empty { dest: "./".into() });
+        assert_eq!(install("Plugins/evil.dll"), InstallError::Duplicate { dest: "Plugins/evil.dll".into() });
+        let err = build_mod("evil", 2, &[("Libs/a.dll", &[3]), ("./Libs//a.dll", &[4])]).install(&game.0).unwrap_err();
+        assert_eq!(
+            *err.into_inner().unwrap().downcast::<InstallError>().unwrap(),
+            InstallError::Duplicate { dest: "Libs/a.dll".into() }
+        );
+
+        #[cfg(unix)]
+        {
+            let outside = TempDir::new("install-outside");
+            std::os::unix::fs::symlink(&outside.0, game.0.join("UserData/link")).unwrap();
+            assert_eq!(install("UserData/link/x.dll"), InstallError::Escape { dest: "UserData/link/x.dll".into() });
+            assert!(std::fs::read_dir(&outside.0).unwrap().next().is_none());
+
+            // links that stay inside are fine
+            std::os::unix::fs::symlink(game.0.join("Libs"), game.0.join("UserData/libs")).unwrap();
+            build_mod("ok", 2, &[("UserData/libs/other.dll", &[4])]).install(&game.0).unwrap();
+            assert_eq!(std::fs::read(game.0.join("Libs/other.dll")).unwrap(), [4]);
+        }
+
+        // nothing of a rejected mod is written
+        assert!(!game.0.join("Plugins/evil.dll").exists());
+    }
+
+    #[test]
+    fn test_uninstall() {
+        let game = TempDir::new("uninstall");
+
+        build_mod("pp", 1, &[("UserData/pp/a.json", &[1]), ("UserData/pp/old.json", &[2])]).install(&game.0).unwrap();
+        build_mod("other", 1, &[("Libs/shared.dll", &[3])]).install(&game.0).unwrap();
+
+        let state = InstallState::load(&game.0).unwrap();
+        assert_eq!(state.receipts.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["other", "pp"]);
+        let receipt = state.receipt("pp").unwrap();
+        assert_eq!(receipt.version, Some(Version::new(0, 1, 1)));
+        assert_eq!(receipt.files[1].sha256, sha256(&[1]));
+        assert!(String::from_utf8(std::fs::read(game.0.join(STATE_FILE)).unwrap())
+            .unwrap()
+            .contains("4bf5122f344554c53bde2ebb8cd2b7e3d1600ad631c385a5d7cce23c7785459a"));
+
+        // upgrading takes out what the new version no longer has
+        build_mod("pp", 2, &[("UserData/pp/a.json", &[1]), ("Libs/shared.dll", &[3])]).install(&game.0).unwrap();
+        assert!(!game.0.join("UserData/pp/old.json").exists());
+        assert_eq!(InstallState::load(&game.0).unwrap().receipt("pp").unwrap().version, Some(Version::new(0, 1, 2)));
+
+        // the user changed a.json, other still has shared.dll
+        std::fs::write(game.0.join("UserData/pp/a.json"), b"edited").unwrap();
+        let report = uninstall(&game.0, "pp").unwrap();
+        assert_eq!(report.removed, ["Plugins/pp.dll"]);
+        assert_eq!(report.kept, ["Libs/shared.dll", "UserData/pp/a.json"]);
+        assert_eq!(std::fs::read(game.0.join("UserData/pp/a.json")).unwrap(), b"edited");
+        assert!(InstallState::load(&game.0).unwrap().receipt("pp").is_none());
+
+        let err = uninstall(&game.0, "pp").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+
+        let report = uninstall(&game.0, "other").unwrap();
+        assert_eq!(report.removed, ["Plugins/other.dll", "Libs/shared.dll"]);
+        assert!(InstallState::load(&game.0).unwrap().receipts.is_empty());
+    }
+
+    #[test]
+    fn test_install_rollback() {
+        use std::collections::BTreeMap;
+
+        struct Hooks {
+            ran: Vec<(HookStage, PathBuf)>,
+            fail: Option<HookStage>,
+        }
+
+        impl InstallHooks for Hooks {
+            fn run(&mut self, stage: HookStage, program: &std::path::Path, _: &std::path::Path) -> std::io::Result<()> {
+                self.ran.push((stage, program.to_path_buf()));
+                match self.fail == Some(stage) {
+                    true => Err(std::io::Error::other("hook failed")),
+                    false => Ok(()),
+                }
+            }
+        }
+
+        let game = TempDir::new("install-rollback");
+        let snapshot = || {
+            walkdir::WalkDir::new(&game.0)
+                .into_iter()
+                .map(|e| e.unwrap())
+                .map(|e| (e.path().to_path_buf(), std::fs::read(e.path()).ok()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let mut hooks = Hooks { ran: vec![], fail: None };
+
+        // an empty game folder is left empty, the folders of the transaction included
+        let empty = TempDir::new("install-rollback-empty");
+        let v1 = build_mod("pp", 1, &[("UserData/pp/a.json", b"1")]);
+        let mut failures = 0;
+        loop {
+            FAIL_AFTER.with(|f| f.set(Some(failures)));
+            let result = v1.install_with(&empty.0, ConflictPolicy::default(), &mut hooks);
+            FAIL_AFTER.with(|f| f.set(None));
+
+            match result {
+                Ok(_) => break,
+                Err(e) => {
+                    assert_eq!(e.to_string(), "injected failure");
+                    assert!(std::fs::read_dir(&empty.0).unwrap().next().is_none(), "left behind after {} steps", failures);
+                },
+            }
+            failures += 1;
+        }
+        assert!(failures > 4);
+        assert_eq!(InstallState::load(&empty.0).unwrap().receipt("pp").unwrap().version, Some(Version::new(0, 1, 1)));
+
+        game.write("UserData/pp/config.json", b"user");
+        build_mod("pp", 1, &[("UserData/pp/a.json", b"1"), ("UserData/pp/old.json", b"1")])
+            .install_with(&game.0, ConflictPolicy::default(), &mut hooks)
+            .unwrap();
+
+        // replaces a user file, adds folders and takes out a stale file
+        let v2 = build_mod_with_hook(
+            "pp",
+            2,
+            &[
+                ("UserData/pp/a.json", b"2"),
+                ("UserData/pp/config.json", b"2"),
+                ("Libs/pp/deep/x.dll", b"2"),
+            ],
+            Some("bin/post.sh"),
+        );
+
+        let mut failures = 0;
+        loop {
+            let before = snapshot();
+            FAIL_AFTER.with(|f| f.set(Some(failures)));
+            let result = v2.install_with(&game.0, ConflictPolicy::default(), &mut hooks);
+            FAIL_AFTER.with(|f| f.set(None));
+
+            match result {
+                Ok(_) => break,
+                Err(e) => {
+                    assert_eq!(e.to_string(), "injected failure");
+                    assert_eq!(snapshot(), before, "not rolled back after {} steps", failures);
+                },
+            }
+            failures += 1;
+        }
+        assert!(failures > 8);
+
+        assert_eq!(std::fs::read(game.0.join("UserData/pp/config.json")).unwrap(), b"2");
+        assert_eq!(std::fs::read(game.0.join("Libs/pp/deep/x.dll")).unwrap(), b"2");
+        assert!(!game.0.join("UserData/pp/old.json").exists());
+        assert!(!game.0.join(TRANSACTION_DIR).join("pp").exists());
+        assert_eq!(hooks.ran.last().unwrap(), &(HookStage::Post, game.0.join("bin/post.sh")));
+
+        // a failing hook rolls back as well
+        let before = snapshot();
+        let mut failing = Hooks {
+            ran: vec![],
+            fail: Some(HookStage::Post),
+        };
+        let v3 = build_mod_with_hook("pp", 3, &[("UserData/pp/a.json", b"3")], Some("bin/post.sh"));
+        assert_eq!(v3.install_with(&game.0, ConflictPolicy::default(), &mut failing).unwrap_err().to_string(), "hook failed");
+        assert_eq!(snapshot(), before);
+        assert_eq!(InstallState::load(&game.0).unwrap().receipt("pp").unwrap().version, Some(Version::new(0, 1, 2)));
+
+        // hooks are held to the same rules as files
+        let evil = build_mod_with_hook("pp", 3, &[], Some("../evil.sh"));
+        let err = evil.install_with(&game.0, ConflictPolicy::default(), &mut hooks).unwrap_err();
+        assert_eq!(
+            *err.into_inner().unwrap().downcast::<InstallError>().unwrap(),
+            InstallError::Traversal { dest: "../evil.sh".into() }
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for (script, code) in [("bin/ok.sh", 0), ("bin/fail.sh", 1)] {
+                game.write(script, format!("#!/bin/sh\nexit {}\n", code).as_bytes());
+                std::fs::set_permissions(game.0.join(script), std::fs::Permissions::from_mode(0o755)).unwrap();
+            }
+            build_mod_with_hook("pp", 4, &[], Some("bin/ok.sh")).install(&game.0).unwrap();
+            let err = build_mod_with_hook("pp", 5, &[], Some("bin/fail.sh")).install(&game.0).unwrap_err();
+            assert!(matches!(*err.into_inner().unwrap().downcast::<InstallError>().unwrap(), InstallError::Hook { .. }));
+            assert_eq!(InstallState::load(&game.0).unwrap().receipt("pp").unwrap().version, Some(Version::new(0, 1, 4)));
+        }
+
+        // leftovers of an interrupted install are never overwritten
+        std::fs::create_dir_all(game.0.join(TRANSACTION_DIR).join("pp")).unwrap();
+        let err = v3.install_with(&game.0, ConflictPolicy::default(), &mut hooks).unwrap_err();
+        assert_eq!(
+            *err.into_inner().unwrap().downcast::<InstallError>().unwrap(),
+            InstallError::Unfinished { id: "pp".into() }
+        );
+
+        // and an _id cannot reach them through the transaction folder
+        let mut dotted = match build_mod("pp", 3, &[("UserData/pp/a.json", b"3")]) {
+            ForgeModTypes::Mod(m) => m,
+            _ => unreachable!(),
+        };
+        dotted.manifest._id = "pp/..".into();
+        dotted.update_checksums();
+        let err = ForgeModTypes::Mod(dotted).install_with(&game.0, ConflictPolicy::default(), &mut hooks).unwrap_err();
+        assert_eq!(
+            *err.into_inner().unwrap().downcast::<InstallError>().unwrap(),
+            InstallError::InvalidId { id: "pp/..".into() }
+        );
+        assert!(game.0.join(TRANSACTION_DIR).join("pp").exists());
+    }
+
+    #[test]
+    fn test_plan_install() {
+        let game = TempDir::new("plan-install");
+
+        build_mod("other", 1, &[("Libs/shared.dll", b"shared")]).install(&game.0).unwrap();
+        build_mod("pp", 1, &[("UserData/pp/old.json", b"1")]).install(&game.0).unwrap();
+        game.write("UserData/pp/config.json", b"user");
+
+        let snapshot = || {
+            walkdir::WalkDir::new(&game.0)
+                .into_iter()
+                .map(|e| e.unwrap())
+                .map(|e| (e.path().to_path_buf(), std::fs::read(e.path()).ok()))
+                .collect::<Vec<_>>()
+        };
+        let before = snapshot();
+
+        let v2 = build_mod_with_hook(
+            "pp",
+            2,
+            &[("Libs/shared.dll", b"shared"), ("UserData/pp/config.json", b"{}"), ("UserData/pp/new.json", b"new")],
+            Some("bin/post.sh"),
+        );
+        let plan = plan_install(&game.0, &v2, ConflictPolicy::default()).unwrap();
+        assert_eq!(snapshot(), before);
+
+        assert_eq!(plan.id, "pp");
+        assert_eq!(plan.version, Some(Version::new(0, 1, 2)));
+        assert_eq!(plan.create, ["UserData/pp/new.json"]);
+        assert_eq!(plan.overwrite, ["Plugins/pp.dll", "Libs/shared.dll", "UserData/pp/config.json"]);
+        let owners: Vec<_> = plan.conflicts.iter().map(|c| (c.path.as_str(), c.existing.id.as_str())).collect();
+        assert_eq!(owners, [("Libs/shared.dll", "other")]);
+        assert_eq!(plan.remove, ["UserData/pp/old.json"]);
+        assert_eq!(plan.bytes, 2 + 6 + 2 + 3);
+        assert_eq!(plan.hooks, [(HookStage::Post, game.0.join("bin/post.sh"))]);
+
+        // the plan fails where the install would
+        let evil = build_mod("evil", 1, &[("../x.dll", b"x")]);
+        assert!(plan_install(&game.0, &evil, ConflictPolicy::default()).is_err());
+        std::fs::create_dir_all(game.0.join("UserData/dir")).unwrap();
+        let dir = build_mod("dir", 1, &[("UserData/dir", b"x")]);
+        assert_eq!(plan_install(&game.0, &dir, ConflictPolicy::default()).unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+        assert!(dir.install(&game.0).is_err());
+        assert!(!game.0.join("Plugins/dir.dll").exists());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let game = TempDir::new("conflicts");
+        let conflict = |err: std::io::Error| *err.into_inner().unwrap().downcast::<InstallError>().unwrap();
+
+        build_mod("a", 1, &[("Libs/same.dll", b"S"), ("Libs/shared.dll", b"A")]).install(&game.0).unwrap();
+        let b = build_mod("b", 2, &[("Libs/same.dll", b"S"), ("Libs/shared.dll", b"B")]);
+
+        // identical files are fine by default, different ones are not
+        let err = b.install(&game.0).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(err.to_string(), "Libs/shared.dll of b is already installed by a");
+        assert_eq!(
+            conflict(err),
+            InstallError::Conflict {
+                path: "Libs/shared.dll".into(),
+                existing: "a".into(),
+                incoming: "b".into(),
+            }
+        );
+        assert!(!game.0.join("Plugins/b.dll").exists());
+
+        let mut hooks = CommandHooks;
+        let err = b.install_with(&game.0, ConflictPolicy::Fail, &mut hooks).unwrap_err();
+        assert!(matches!(conflict(err), InstallError::Conflict { path, .. } if path == "Libs/same.dll"));
+
+        // the higher version wins, the lower one does not write the file
+        b.install_with(&game.0, ConflictPolicy::HigherVersion, &mut hooks).unwrap();
+        assert_eq!(std::fs::read(game.0.join("Libs/shared.dll")).unwrap(), b"B");
+
+        // and the file is no longer a's
+        let shared: Vec<_> = InstallState::load(&game.0)
+            .unwrap()
+            .receipts
+            .iter()
+            .filter(|r| r.files.iter().any(|f| f.path == "Libs/shared.dll"))
+            .map(|r| r.id.clone())
+            .collect();
+        assert_eq!(shared, ["b"]);
+
+        // the plan settles conflicts the way the install does
+        let c = build_mod("c", 0, &[("Libs/shared.dll", b"C")]);
+        let err = plan_install(&game.0, &c, ConflictPolicy::default()).unwrap_err();
+        assert!(matches!(conflict(err), InstallError::Conflict { path, .. } if path == "Libs/shared.dll"));
+        let plan = plan_install(&game.0, &c, ConflictPolicy::HigherVersion).unwrap();
+        assert_eq!(plan.create, ["Plugins/c.dll"]);
+        assert!(plan.overwrite.is_empty());
+        assert_eq!(plan.bytes, 2);
+        let owners: Vec<_> = plan.conflicts.iter().map(|c| (c.path.as_str(), c.existing.id.as_str())).collect();
+        assert_eq!(owners, [("Libs/shared.dll", "b")]);
+        assert_eq!(plan.conflicts[0].existing, FileOwner {
+            id: "b".into(),
+            version: Some(Version::new(0, 1, 2)),
+            sha256: sha256(b"B"),
+        });
+        assert_eq!(plan.conflicts[0].incoming.sha256, sha256(b"C"));
+
+        let receipt = c.install_with(&game.0, ConflictPolicy::HigherVersion, &mut hooks).unwrap();
+        assert_eq!(receipt.files.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), ["Plugins/c.dll"]);
+        assert_eq!(std::fs::read(game.0.join("Libs/shared.dll")).unwrap(), b"B");
+
+        // packages about to be installed are checked against each other as well
+        let x = build_mod("x", 1, &[("Libs/x.dll", b"X")]);
+        let y = build_mod("y", 1, &[("Libs/x.dll", b"Y"), ("Libs/same.dll", b"S")]);
+        let conflicts = find_conflicts(&game.0, &[x, y]).unwrap();
+        let found: Vec<_> = conflicts.iter().map(|c| (c.path.as_str(), c.existing.id.as_str(), c.incoming.id.as_str())).collect();
+        assert_eq!(found, [("Libs/same.dll", "a", "y"), ("Libs/same.dll", "b", "y"), ("Libs/x.dll", "x", "y")]);
+
+        // reinstalling the same mod is not a conflict
+        assert!(find_conflicts(&game.0, &[build_mod("c", 1, &[])]).unwrap().is_empty());
+
+        // nothing is left once every mod is gone
+        for id in ["b", "a", "c"] {
+            uninstall(&game.0, id).unwrap();
+        }
+        assert!(!game.0.join("Libs/shared.dll").exists());
+        assert!(!game.0.join("Libs/same.dll").exists());
+    }
+}